@@ -1,13 +1,13 @@
 use std::{fmt::Debug, string::FromUtf8Error};
 
 use reqwest::{
-    header::{ACCEPT, CONTENT_TYPE},
+    header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER},
     Method, Response, StatusCode,
 };
 use serde::de::DeserializeOwned;
 use tracing::{error, instrument, trace};
 
-use crate::{Change, Changes, DomainFilter, Endpoint};
+use crate::{Change, Changes, DomainFilter, Endpoint, ErrorBody};
 
 pub use url::Url;
 
@@ -51,6 +51,10 @@ pub enum Error {
     #[error("webhook: status code {0}: {1}")]
     Webhook(StatusCode, String),
 
+    /// Webhook Failure with a structured error body.
+    #[error("provider: status code {0}: {kind:?}: {message}", kind = .1.kind, message = .1.message)]
+    Provider(StatusCode, ErrorBody),
+
     /// Response payload is not valid utf8
     #[error("invalid utf8 payload: {0}")]
     InvalidUtf8(#[from] FromUtf8Error),
@@ -75,15 +79,15 @@ impl Client {
 
     /// Initialize the webhook service and fetch the domain filter.
     #[instrument(skip(self))]
-    pub async fn init(&self) -> Result<Vec<String>, Error> {
-        Ok(self
+    pub async fn init(&self) -> Result<DomainFilter, Error> {
+        let response = self
             .client
             .get(self.domain.clone())
+            .header(ACCEPT, "application/external.dns.webhook+json;version=1")
             .send()
-            .await?
-            .json::<DomainFilter>()
-            .await?
-            .filters)
+            .await?;
+
+        Self::parse_response(response).await
     }
 
     /// Check health of the webhook service
@@ -119,10 +123,28 @@ impl Client {
             return Ok(());
         }
 
-        Err(Error::Webhook(
-            response.status(),
-            String::from_utf8_lossy(&response.bytes().await?).into_owned(),
-        ))
+        Err(Self::webhook_error(response).await?)
+    }
+
+    /// Decode an unsuccessful response into either a [`Error::Provider`],
+    /// if the webhook returned a structured error body, or [`Error::Webhook`].
+    async fn webhook_error(response: Response) -> Result<Error, Error> {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        let payload = String::from_utf8_lossy(&response.bytes().await?).into_owned();
+
+        match serde_json::from_str::<ErrorBody>(&payload) {
+            Ok(mut body) => {
+                body.retry_after = body.retry_after.or(retry_after);
+                Ok(Error::Provider(status, body))
+            }
+            Err(_) => Ok(Error::Webhook(status, payload)),
+        }
     }
 
     async fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
@@ -130,6 +152,10 @@ impl Client {
 
         trace!("webhook returned status code: {status}");
 
+        if !status.is_success() {
+            return Err(Self::webhook_error(response).await?);
+        }
+
         let payload = response
             .bytes()
             .await
@@ -144,17 +170,13 @@ impl Client {
             err
         })?;
 
-        if status.is_success() {
-            let payload = serde_json::from_str::<T>(&payload).map_err(|err| {
-                error!("failed to parse json payload: {err} ({payload})");
-                Error::Deserialization(err)
-            })?;
+        let payload = serde_json::from_str::<T>(&payload).map_err(|err| {
+            error!("failed to parse json payload: {err} ({payload})");
+            Error::Deserialization(err)
+        })?;
 
-            trace!("api returned response: {payload:?}");
-            Ok(payload)
-        } else {
-            Err(Error::Webhook(status, payload))
-        }
+        trace!("api returned response: {payload:?}");
+        Ok(payload)
     }
 
     /// Get all records.
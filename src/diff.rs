@@ -0,0 +1,701 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    net::IpAddr,
+    str::FromStr,
+};
+
+use kubizone_common::Type;
+use serde::{Deserialize, Serialize};
+
+use crate::{plan::cname_conflicts, Change, Conflict, Endpoint, Plan};
+
+/// Utility trait for computing a differential from two lists of endpoints.
+pub trait EndpointDiff {
+    /// Compare current (self) and desired (other) state, and compute a list of
+    /// changes to move from one state to the other.
+    ///
+    /// * Endpoints contained in `self` but not in `other` will yield a [`Change::Delete`].
+    /// * Endpoints contained in `other` but not in `self` will yield a [`Change::Create`].
+    /// * Endpoints contained in both `self` and `other` will yield a [`Change::Update`],
+    ///   *if* the entries are not equivalent.
+    ///
+    /// Targets are compared using [`targets_equivalent`], so reordered or
+    /// differently formatted targets do not produce spurious updates.
+    ///
+    /// Endpoints are matched by their [`EndpointIdent`](crate::EndpointIdent) *and* `set_identifier`, so
+    /// routing-policy records sharing a name and type are diffed independently.
+    /// If either list contains the same endpoint more than once, only its last
+    /// occurrence is used. Changes are ordered as the endpoints appear in the inputs.
+    fn difference(self, other: Self) -> Vec<Change>;
+
+    /// Like [`EndpointDiff::difference`], but compares endpoints according
+    /// to the given [`DiffOptions`].
+    fn difference_with(self, other: Self, options: &DiffOptions) -> Vec<Change>;
+
+    /// Like [`EndpointDiff::difference_with`], but also reports any conflicts
+    /// which prevented changes from being produced, or which exist within the
+    /// desired state itself.
+    fn plan(self, other: Self, options: &DiffOptions) -> Plan;
+}
+
+/// Label used by External-DNS to mark the owner of an endpoint.
+pub const OWNER_LABEL: &str = "owner";
+
+/// Options controlling how endpoints are compared by [`EndpointDiff::difference_with`].
+///
+/// Useful for backends which do not store some of the [`Endpoint`] fields, and
+/// would otherwise produce an update for every record on every reconciliation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Disregard differences in `labels`.
+    pub ignore_labels: bool,
+
+    /// Disregard differences in `provider_specific` properties.
+    pub ignore_provider_specific: bool,
+
+    /// Disregard differences in `record_ttl`.
+    pub ignore_ttl: bool,
+
+    /// Which kinds of changes to produce. Defaults to [`Policy::Sync`].
+    pub policy: Policy,
+
+    /// Owner ID of the managing controller, if any.
+    ///
+    /// If set, only endpoints whose [`OWNER_LABEL`] matches the owner ID are
+    /// deleted or updated. Endpoints owned by someone else are never deleted,
+    /// and attempts to update them are reported as a [`Conflict::ForeignOwner`]
+    /// instead. Desired endpoints are labeled with the owner ID.
+    pub owner: Option<String>,
+}
+
+impl DiffOptions {
+    /// Disregard differences in `labels`.
+    pub fn ignoring_labels(mut self) -> Self {
+        self.ignore_labels = true;
+        self
+    }
+
+    /// Disregard differences in `provider_specific` properties.
+    pub fn ignoring_provider_specific(mut self) -> Self {
+        self.ignore_provider_specific = true;
+        self
+    }
+
+    /// Disregard differences in `record_ttl`.
+    pub fn ignoring_ttl(mut self) -> Self {
+        self.ignore_ttl = true;
+        self
+    }
+
+    /// Only produce the kinds of changes permitted by the given policy.
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Only delete or update endpoints owned by the given owner ID.
+    pub fn with_owner<S: ToString>(mut self, owner: S) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    /// Returns true if the endpoint may be changed, given the configured owner.
+    pub fn owns(&self, endpoint: &Endpoint) -> bool {
+        match &self.owner {
+            Some(owner) => endpoint.labels.get(OWNER_LABEL) == Some(owner),
+            None => true,
+        }
+    }
+
+    /// Returns true if the two endpoints are equivalent, disregarding
+    /// the ignored fields, and comparing targets using [`targets_equivalent`].
+    pub fn equivalent(&self, old: &Endpoint, new: &Endpoint) -> bool {
+        old.identity == new.identity
+            && old.set_identifier == new.set_identifier
+            && targets_equivalent(old.identity.record_type, &old.targets, &new.targets)
+            && (self.ignore_ttl || old.record_ttl == new.record_ttl)
+            && (self.ignore_labels || old.labels == new.labels)
+            && (self.ignore_provider_specific || old.provider_specific == new.provider_specific)
+    }
+}
+
+impl EndpointDiff for Vec<Endpoint> {
+    fn difference(self, other: Self) -> Vec<Change> {
+        self.difference_with(other, &DiffOptions::default())
+    }
+
+    fn difference_with(self, other: Self, options: &DiffOptions) -> Vec<Change> {
+        self.plan(other, options).changes
+    }
+
+    fn plan(self, other: Self, options: &DiffOptions) -> Plan {
+        // Claim ownership of all desired endpoints, so they match the current
+        // state once created, and don't produce an update on the next cycle.
+        let other: Vec<Endpoint> = match &options.owner {
+            Some(owner) => other
+                .into_iter()
+                .map(|mut endpoint| {
+                    endpoint
+                        .labels
+                        .insert(OWNER_LABEL.to_string(), owner.clone());
+                    endpoint
+                })
+                .collect(),
+            None => other,
+        };
+
+        let old: HashMap<_, &Endpoint> =
+            HashMap::from_iter(self.iter().map(|endpoint| (endpoint.key(), endpoint)));
+        let new: HashMap<_, &Endpoint> =
+            HashMap::from_iter(other.iter().map(|endpoint| (endpoint.key(), endpoint)));
+
+        // Each key is diffed once, using its last occurrence in either list.
+        let current = self
+            .iter()
+            .filter(|endpoint| std::ptr::eq(old[&endpoint.key()], *endpoint));
+        let desired = other
+            .iter()
+            .filter(|endpoint| std::ptr::eq(new[&endpoint.key()], *endpoint));
+
+        let mut plan = Plan::default();
+
+        // Changes are emitted in the order the endpoints appear in the
+        // input lists, so the output is stable across runs.
+        for endpoint in current.clone() {
+            if !new.contains_key(&endpoint.key()) && options.owns(endpoint) {
+                plan.changes.push(Change::Delete(endpoint.clone()));
+            }
+        }
+
+        for old in current {
+            let Some(new) = new.get(&old.key()) else {
+                continue;
+            };
+
+            if options.equivalent(old, new) {
+                continue;
+            }
+
+            if options.owns(old) {
+                plan.changes.push(Change::Update {
+                    old: old.clone(),
+                    new: (*new).clone(),
+                });
+            } else {
+                plan.conflicts.push(Conflict::ForeignOwner {
+                    current: Box::new(old.clone()),
+                    desired: Box::new((*new).clone()),
+                });
+            }
+        }
+
+        for endpoint in desired {
+            if !old.contains_key(&endpoint.key()) {
+                plan.changes.push(Change::Create(endpoint.clone()));
+            }
+        }
+
+        plan.changes.retain(|change| options.policy.permits(change));
+        plan.conflicts.extend(cname_conflicts(&other));
+        plan
+    }
+}
+
+/// Synchronization policy, mirroring the `--policy` flag of External-DNS.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// Create, update and delete records as needed.
+    #[default]
+    Sync,
+
+    /// Create and update records, but never delete them.
+    UpsertOnly,
+
+    /// Only create records, never update or delete them.
+    CreateOnly,
+}
+
+impl Policy {
+    /// Returns true if the change is permitted under this policy.
+    pub fn permits(&self, change: &Change) -> bool {
+        match (self, change) {
+            (Policy::Sync, _) => true,
+            (Policy::UpsertOnly, Change::Delete(_)) => false,
+            (Policy::UpsertOnly, _) => true,
+            (Policy::CreateOnly, Change::Create(_)) => true,
+            (Policy::CreateOnly, _) => false,
+        }
+    }
+
+    /// Strip any changes not permitted under this policy.
+    pub fn apply(&self, changes: Vec<Change>) -> Vec<Change> {
+        changes
+            .into_iter()
+            .filter(|change| self.permits(change))
+            .collect()
+    }
+}
+
+/// Produced when parsing an unknown [`Policy`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown policy {0:?}, expected one of sync, upsert-only or create-only")]
+pub struct UnknownPolicy(pub String);
+
+impl FromStr for Policy {
+    type Err = UnknownPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sync" => Ok(Policy::Sync),
+            "upsert-only" => Ok(Policy::UpsertOnly),
+            "create-only" => Ok(Policy::CreateOnly),
+            other => Err(UnknownPolicy(other.to_string())),
+        }
+    }
+}
+
+impl Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Sync => f.write_str("sync"),
+            Policy::UpsertOnly => f.write_str("upsert-only"),
+            Policy::CreateOnly => f.write_str("create-only"),
+        }
+    }
+}
+
+/// Compare two lists of targets of the given record type.
+///
+/// Targets are treated as a set, so order and duplicates are disregarded,
+/// and each target is normalized using [`normalize_target`] before comparison.
+pub fn targets_equivalent(record_type: Type, a: &[String], b: &[String]) -> bool {
+    let normalize = |targets: &[String]| -> BTreeSet<String> {
+        targets
+            .iter()
+            .map(|target| normalize_target(record_type, target))
+            .collect()
+    };
+
+    normalize(a) == normalize(b)
+}
+
+/// Normalize a target of the given record type into its canonical form.
+///
+/// * `A` and `AAAA` targets are parsed and re-printed as IP addresses.
+/// * Hostname targets of `CNAME`, `NS`, `PTR` and `DNAME` records, as well as the
+///   trailing hostname of `MX` and `SRV` targets, are lowercased and dot-terminated.
+/// * `TXT` values have the surrounding double quotes External-DNS may wrap them in removed.
+/// * All other targets are left untouched.
+pub fn normalize_target(record_type: Type, target: &str) -> String {
+    match record_type {
+        Type::A | Type::AAAA => target
+            .trim()
+            .parse::<IpAddr>()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|_| target.to_string()),
+        Type::CNAME | Type::NS | Type::PTR | Type::DNAME => normalize_hostname(target),
+        Type::TXT => target
+            .strip_prefix('"')
+            .and_then(|target| target.strip_suffix('"'))
+            .unwrap_or(target)
+            .to_string(),
+        Type::MX | Type::SRV => {
+            let mut fields: Vec<String> = target.split_whitespace().map(str::to_string).collect();
+
+            if let Some(hostname) = fields.last_mut() {
+                *hostname = normalize_hostname(hostname);
+            }
+
+            fields.join(" ")
+        }
+        _ => target.to_string(),
+    }
+}
+
+/// Normalize each target using [`normalize_target`], removing duplicates
+/// while preserving the order of the remaining targets.
+pub fn normalize_targets(record_type: Type, targets: Vec<String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(targets.len());
+
+    for target in targets {
+        let target = normalize_target(record_type, &target);
+        if !out.contains(&target) {
+            out.push(target);
+        }
+    }
+
+    out
+}
+
+/// Normalize the targets of each endpoint using [`normalize_targets`].
+///
+/// Suitable for implementing `Provider::adjust_endpoints` in providers which
+/// store targets in their canonical form, as it leaves everything but the
+/// targets untouched.
+pub fn normalize_endpoints(endpoints: Vec<Endpoint>) -> Vec<Endpoint> {
+    endpoints
+        .into_iter()
+        .map(|mut endpoint| {
+            endpoint.targets = normalize_targets(endpoint.identity.record_type, endpoint.targets);
+            endpoint
+        })
+        .collect()
+}
+
+fn normalize_hostname(hostname: &str) -> String {
+    let hostname = hostname.trim().to_ascii_lowercase();
+
+    if hostname.is_empty() || hostname.ends_with('.') {
+        hostname
+    } else {
+        hostname + "."
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn difference_calculation() {
+    use crate::EndpointIdent;
+    use kubizone_common::DomainName;
+
+    let a = vec![
+        Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("update.org.").unwrap(),
+                record_type: Type::A,
+            },
+            set_identifier: None,
+            targets: vec!["192.168.0.1".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        },
+        Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("delete.org.").unwrap(),
+                record_type: Type::A,
+            },
+            set_identifier: None,
+            targets: vec!["192.168.0.1".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        },
+    ];
+
+    let b = vec![
+        Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("update.org.").unwrap(),
+                record_type: Type::A,
+            },
+            set_identifier: None,
+            targets: vec!["192.168.0.2".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        },
+        Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("create.org.").unwrap(),
+                record_type: Type::A,
+            },
+            set_identifier: None,
+            targets: vec!["192.168.0.1".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        },
+    ];
+
+    let changes = a.difference(b);
+
+    assert_eq!(
+        changes,
+        vec![
+            Change::Delete(Endpoint {
+                identity: EndpointIdent {
+                    dns_name: DomainName::try_from("delete.org.").unwrap(),
+                    record_type: Type::A,
+                },
+                set_identifier: None,
+                targets: vec!["192.168.0.1".to_string()],
+                record_ttl: Some(300),
+                labels: HashMap::default(),
+                provider_specific: Vec::new(),
+            }),
+            Change::Update {
+                old: Endpoint {
+                    identity: EndpointIdent {
+                        dns_name: DomainName::try_from("update.org.").unwrap(),
+                        record_type: Type::A,
+                    },
+                    set_identifier: None,
+                    targets: vec!["192.168.0.1".to_string()],
+                    record_ttl: Some(300),
+                    labels: HashMap::default(),
+                    provider_specific: Vec::new(),
+                },
+                new: Endpoint {
+                    identity: EndpointIdent {
+                        dns_name: DomainName::try_from("update.org.").unwrap(),
+                        record_type: Type::A,
+                    },
+                    set_identifier: None,
+                    targets: vec!["192.168.0.2".to_string()],
+                    record_ttl: Some(300),
+                    labels: HashMap::default(),
+                    provider_specific: Vec::new(),
+                }
+            },
+            Change::Create(Endpoint {
+                identity: EndpointIdent {
+                    dns_name: DomainName::try_from("create.org.").unwrap(),
+                    record_type: Type::A,
+                },
+                set_identifier: None,
+                targets: vec!["192.168.0.1".to_string()],
+                record_ttl: Some(300),
+                labels: HashMap::default(),
+                provider_specific: Vec::new(),
+            })
+        ]
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn difference_with_set_identifiers() {
+    let endpoint = |set_identifier: &str, target: &str| Endpoint {
+        set_identifier: Some(set_identifier.to_string()),
+        ..Endpoint::test("weighted.example.org.", Type::A, &[target])
+    };
+
+    let current = vec![endpoint("a", "192.168.0.1"), endpoint("b", "192.168.0.2")];
+    let desired = vec![endpoint("b", "192.168.0.3"), endpoint("c", "192.168.0.4")];
+
+    assert_eq!(
+        current.difference(desired),
+        vec![
+            Change::Delete(endpoint("a", "192.168.0.1")),
+            Change::Update {
+                old: endpoint("b", "192.168.0.2"),
+                new: endpoint("b", "192.168.0.3"),
+            },
+            Change::Create(endpoint("c", "192.168.0.4")),
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn difference_ordering_and_duplicates() {
+    let endpoint = |name: &str, target: &str| Endpoint::test(name, Type::A, &[target]);
+
+    let current = vec![
+        endpoint("b.org.", "192.168.0.1"),
+        endpoint("a.org.", "192.168.0.1"),
+        endpoint("dup.org.", "192.168.0.1"),
+        endpoint("dup.org.", "192.168.0.2"),
+        endpoint("gone.org.", "192.168.0.1"),
+        endpoint("gone.org.", "192.168.0.1"),
+    ];
+    let desired = vec![
+        endpoint("a.org.", "192.168.0.2"),
+        endpoint("b.org.", "192.168.0.2"),
+        endpoint("dup.org.", "192.168.0.3"),
+        endpoint("new.org.", "192.168.0.1"),
+        endpoint("new.org.", "192.168.0.2"),
+    ];
+
+    // Changes follow the order of the inputs, and duplicate keys produce a
+    // single change based on their last occurrence.
+    assert_eq!(
+        current.difference(desired),
+        vec![
+            Change::Delete(endpoint("gone.org.", "192.168.0.1")),
+            Change::Update {
+                old: endpoint("b.org.", "192.168.0.1"),
+                new: endpoint("b.org.", "192.168.0.2"),
+            },
+            Change::Update {
+                old: endpoint("a.org.", "192.168.0.1"),
+                new: endpoint("a.org.", "192.168.0.2"),
+            },
+            Change::Update {
+                old: endpoint("dup.org.", "192.168.0.2"),
+                new: endpoint("dup.org.", "192.168.0.3"),
+            },
+            Change::Create(endpoint("new.org.", "192.168.0.2")),
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn normalized_target_comparison() {
+    let targets = |targets: &[&str]| targets.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+    assert!(targets_equivalent(
+        Type::A,
+        &targets(&["1.1.1.1", "2.2.2.2"]),
+        &targets(&["2.2.2.2", "1.1.1.1", "1.1.1.1"])
+    ));
+    assert!(targets_equivalent(
+        Type::AAAA,
+        &targets(&["2001:0db8:0000:0000:0000:0000:0000:0001"]),
+        &targets(&["2001:db8::1"])
+    ));
+    assert!(targets_equivalent(
+        Type::CNAME,
+        &targets(&["WWW.Example.org"]),
+        &targets(&["www.example.org."])
+    ));
+    assert!(targets_equivalent(
+        Type::MX,
+        &targets(&["10 Mail.Example.org"]),
+        &targets(&["10  mail.example.org."])
+    ));
+    assert!(!targets_equivalent(
+        Type::TXT,
+        &targets(&["Hello"]),
+        &targets(&["hello"])
+    ));
+    assert!(targets_equivalent(
+        Type::TXT,
+        &targets(&["\"hello\""]),
+        &targets(&["hello"])
+    ));
+    assert!(!targets_equivalent(
+        Type::A,
+        &targets(&["1.1.1.1"]),
+        &targets(&["1.1.1.1", "2.2.2.2"])
+    ));
+    assert_eq!(
+        normalize_targets(Type::CNAME, targets(&["B.org", "a.org.", "b.org."])),
+        targets(&["b.org.", "a.org."])
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn difference_with_ignored_fields() {
+    use crate::ProviderSpecificProperty;
+
+    let current = vec![Endpoint::test("example.org.", Type::A, &["192.168.0.1"])];
+
+    let desired = vec![Endpoint {
+        record_ttl: Some(300),
+        labels: HashMap::from([("owner".to_string(), "default".to_string())]),
+        provider_specific: vec![ProviderSpecificProperty {
+            name: "proxied".to_string(),
+            value: "true".to_string(),
+        }],
+        ..current[0].clone()
+    }];
+
+    assert_eq!(current.clone().difference(desired.clone()).len(), 1);
+    assert_eq!(
+        current.clone().difference_with(
+            desired.clone(),
+            &DiffOptions::default().ignoring_labels().ignoring_ttl()
+        ),
+        vec![Change::Update {
+            old: current[0].clone(),
+            new: desired[0].clone()
+        }]
+    );
+    assert_eq!(
+        current.difference_with(
+            desired,
+            &DiffOptions::default()
+                .ignoring_labels()
+                .ignoring_ttl()
+                .ignoring_provider_specific()
+        ),
+        vec![]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn difference_with_policy() {
+    let endpoint = |name: &str, target: &str| Endpoint::test(name, Type::A, &[target]);
+
+    let current = vec![
+        endpoint("update.org.", "192.168.0.1"),
+        endpoint("delete.org.", "192.168.0.1"),
+    ];
+    let desired = vec![
+        endpoint("update.org.", "192.168.0.2"),
+        endpoint("create.org.", "192.168.0.1"),
+    ];
+
+    let diff = |policy: &str| {
+        current.clone().difference_with(
+            desired.clone(),
+            &DiffOptions::default().with_policy(policy.parse().unwrap()),
+        )
+    };
+
+    assert_eq!(diff("sync").len(), 3);
+    assert_eq!(
+        diff("upsert-only"),
+        vec![
+            Change::Update {
+                old: endpoint("update.org.", "192.168.0.1"),
+                new: endpoint("update.org.", "192.168.0.2"),
+            },
+            Change::Create(endpoint("create.org.", "192.168.0.1")),
+        ]
+    );
+    assert_eq!(
+        diff("create-only"),
+        vec![Change::Create(endpoint("create.org.", "192.168.0.1"))]
+    );
+    assert!("upsert".parse::<Policy>().is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn difference_with_owner() {
+    let endpoint = |name: &str, target: &str, owner: Option<&str>| Endpoint {
+        labels: HashMap::from_iter(owner.map(|owner| (OWNER_LABEL.to_string(), owner.to_string()))),
+        ..Endpoint::test(name, Type::A, &[target])
+    };
+
+    let current = vec![
+        endpoint("ours.org.", "192.168.0.1", Some("us")),
+        endpoint("theirs.org.", "192.168.0.1", Some("them")),
+        endpoint("unowned.org.", "192.168.0.1", None),
+        endpoint("stale.org.", "192.168.0.1", Some("us")),
+    ];
+    let desired = vec![
+        endpoint("ours.org.", "192.168.0.2", None),
+        endpoint("theirs.org.", "192.168.0.2", None),
+        endpoint("new.org.", "192.168.0.1", None),
+    ];
+
+    let plan = current.plan(desired, &DiffOptions::default().with_owner("us"));
+
+    assert_eq!(
+        plan.changes,
+        vec![
+            Change::Delete(endpoint("stale.org.", "192.168.0.1", Some("us"))),
+            Change::Update {
+                old: endpoint("ours.org.", "192.168.0.1", Some("us")),
+                new: endpoint("ours.org.", "192.168.0.2", Some("us")),
+            },
+            Change::Create(endpoint("new.org.", "192.168.0.1", Some("us"))),
+        ]
+    );
+    assert_eq!(
+        plan.conflicts,
+        vec![Conflict::ForeignOwner {
+            current: Box::new(endpoint("theirs.org.", "192.168.0.1", Some("them"))),
+            desired: Box::new(endpoint("theirs.org.", "192.168.0.2", Some("us"))),
+        }]
+    );
+}
@@ -0,0 +1,163 @@
+use std::{
+    fmt::{Debug, Formatter},
+    io::{Read, Write},
+};
+
+use aes_gcm::{
+    aead::{Aead, OsRng},
+    AeadCore, Aes256Gcm, Key, KeyInit, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+/// Size of the nonce prepended to encrypted payloads, as used by External-DNS.
+const NONCE_SIZE: usize = 12;
+
+/// AES-256 key used to encrypt TXT registry records, equivalent to
+/// External-DNS' `--txt-encrypt-aes-key`.
+///
+/// The [`Debug`] implementation does not reveal the key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+/// Produced when constructing an [`EncryptionKey`] from a slice which is not 32 bytes long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("encryption key must be 32 bytes long, got {0}")]
+pub struct InvalidKeyLength(pub usize);
+
+/// Produced when a TXT registry payload cannot be decrypted.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecryptionError {
+    /// The payload is not valid base64.
+    #[error("payload is not valid base64")]
+    Base64,
+
+    /// The payload is too short to contain a nonce and ciphertext.
+    #[error("payload is shorter than the {NONCE_SIZE} byte nonce")]
+    TooShort,
+
+    /// The payload was not encrypted with this key, or has been tampered with.
+    #[error("payload could not be authenticated")]
+    Authentication,
+
+    /// The decrypted payload is not a valid UTF-8 gzip stream.
+    #[error("decrypted payload is not valid gzip compressed text")]
+    Decompression,
+}
+
+impl EncryptionKey {
+    /// Construct a key from its raw bytes.
+    pub fn new(key: [u8; 32]) -> Self {
+        EncryptionKey(key)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+
+    /// Compress and encrypt the text, returning the base64 encoded nonce and ciphertext.
+    pub(crate) fn encrypt(&self, text: &str, nonce: &[u8; NONCE_SIZE]) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(text.as_bytes())
+            .expect("writing to a Vec cannot fail");
+        let compressed = encoder.finish().expect("writing to a Vec cannot fail");
+
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(nonce), compressed.as_slice())
+            .expect("payload is within the AES-GCM size limit");
+
+        STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypt and decompress a payload produced by [`EncryptionKey::encrypt`],
+    /// returning the text and the nonce it was encrypted with.
+    pub(crate) fn decrypt(
+        &self,
+        payload: &str,
+    ) -> Result<(String, [u8; NONCE_SIZE]), DecryptionError> {
+        let data = STANDARD
+            .decode(payload)
+            .map_err(|_| DecryptionError::Base64)?;
+
+        if data.len() <= NONCE_SIZE {
+            return Err(DecryptionError::TooShort);
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        let compressed = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DecryptionError::Authentication)?;
+
+        let mut text = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut text)
+            .map_err(|_| DecryptionError::Decompression)?;
+
+        let mut out = [0; NONCE_SIZE];
+        out.copy_from_slice(nonce);
+        Ok((text, out))
+    }
+}
+
+/// Generate a random nonce.
+pub(crate) fn generate_nonce() -> [u8; NONCE_SIZE] {
+    Aes256Gcm::generate_nonce(&mut OsRng).into()
+}
+
+/// Encode a nonce for storage in the [`ENCRYPTION_NONCE_LABEL`](crate::ENCRYPTION_NONCE_LABEL).
+pub(crate) fn encode_nonce(nonce: &[u8; NONCE_SIZE]) -> String {
+    STANDARD.encode(nonce)
+}
+
+/// Decode a nonce stored in the [`ENCRYPTION_NONCE_LABEL`](crate::ENCRYPTION_NONCE_LABEL).
+pub(crate) fn decode_nonce(nonce: &str) -> Option<[u8; NONCE_SIZE]> {
+    STANDARD.decode(nonce).ok()?.try_into().ok()
+}
+
+impl From<[u8; 32]> for EncryptionKey {
+    fn from(key: [u8; 32]) -> Self {
+        EncryptionKey(key)
+    }
+}
+
+impl TryFrom<&[u8]> for EncryptionKey {
+    type Error = InvalidKeyLength;
+
+    fn try_from(key: &[u8]) -> Result<Self, Self::Error> {
+        key.try_into()
+            .map(EncryptionKey)
+            .map_err(|_| InvalidKeyLength(key.len()))
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn payload_encryption() {
+    let key = EncryptionKey::try_from("passphrasewhichneedstobe32bytes!".as_bytes()).unwrap();
+    let text = "heritage=external-dns,external-dns/owner=default";
+
+    let nonce = generate_nonce();
+    let payload = key.encrypt(text, &nonce);
+    assert_eq!(key.decrypt(&payload).unwrap(), (text.to_string(), nonce));
+
+    let other = EncryptionKey::new([0; 32]);
+    assert_eq!(
+        other.decrypt(&payload),
+        Err(DecryptionError::Authentication)
+    );
+    assert_eq!(key.decrypt("AAAA"), Err(DecryptionError::TooShort));
+
+    assert_eq!(
+        EncryptionKey::try_from([0u8; 16].as_slice()),
+        Err(InvalidKeyLength(16))
+    );
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// Category of failure reported by a webhook provider.
+///
+/// Determines the HTTP status code returned by the webhook server, and is
+/// included in structured error bodies, so clients can tell them apart.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// Unexpected failure. Responds with `500 Internal Server Error`.
+    #[default]
+    Internal,
+
+    /// The requested changes are invalid. Responds with `400 Bad Request`.
+    InvalidChanges,
+
+    /// The requested changes conflict with the current state. Responds with `409 Conflict`.
+    Conflict,
+
+    /// The backend is temporarily unavailable. Responds with `503 Service Unavailable`.
+    Unavailable,
+
+    /// The backend is rate limiting requests. Responds with `429 Too Many Requests`.
+    RateLimited,
+}
+
+impl ErrorKind {
+    /// HTTP status code associated with the kind of error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ErrorKind::Internal => 500,
+            ErrorKind::InvalidChanges => 400,
+            ErrorKind::Conflict => 409,
+            ErrorKind::Unavailable => 503,
+            ErrorKind::RateLimited => 429,
+        }
+    }
+}
+
+/// Structured JSON error body returned by the webhook server.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    /// Category of the error.
+    pub kind: ErrorKind,
+
+    /// Human-readable description of the error.
+    pub message: String,
+
+    /// Number of seconds the client should wait before retrying, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
@@ -0,0 +1,382 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use fs4::FileExt;
+use tracing::{debug, instrument};
+
+use crate::{
+    normalize_endpoints, ApplyError, Change, DomainFilter, Endpoint, EndpointSet, ErrorKind,
+    FilteredDomain, Provider, ProviderError,
+};
+
+/// Serialization format of the file backing a [`FileProvider`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    /// Pretty-printed JSON list of endpoints.
+    #[default]
+    Json,
+
+    /// YAML list of endpoints.
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl FileFormat {
+    /// Infer the format from the extension of the path.
+    ///
+    /// Paths ending in `.yaml` or `.yml` are read as YAML if the `yaml`
+    /// feature is enabled, everything else as JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => FileFormat::Yaml,
+            _ => FileFormat::Json,
+        }
+    }
+
+    fn parse(&self, content: &str) -> Result<Vec<Endpoint>, FileError> {
+        Ok(match self {
+            FileFormat::Json => serde_json::from_str(content)?,
+            #[cfg(feature = "yaml")]
+            FileFormat::Yaml => serde_yaml::from_str(content)?,
+        })
+    }
+
+    fn render(&self, endpoints: &[Endpoint]) -> Result<String, FileError> {
+        Ok(match self {
+            FileFormat::Json => serde_json::to_string_pretty(endpoints)? + "\n",
+            #[cfg(feature = "yaml")]
+            FileFormat::Yaml => serde_yaml::to_string(endpoints)?,
+        })
+    }
+}
+
+/// [`Provider`] which persists its endpoints to a JSON or, with the `yaml`
+/// feature, YAML file.
+///
+/// Changes are written to a temporary file which is then renamed over the
+/// original, so readers never observe a partially written file. Access is
+/// serialized across processes using an advisory lock on a `.lock` file
+/// next to the backing file.
+///
+/// The file is reloaded whenever its modification time or size changes, so
+/// edits made by hand (or by `git pull`) are picked up without a restart.
+/// A missing file is treated as empty, and is created on the first change.
+#[derive(Debug, Clone)]
+pub struct FileProvider {
+    path: PathBuf,
+    format: FileFormat,
+    domain_filter: DomainFilter,
+    cache: Arc<Mutex<Cache>>,
+}
+
+/// Error returned by the [`FileProvider`].
+#[derive(Debug, thiserror::Error)]
+pub enum FileError {
+    /// The backing file could not be read or written.
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The backing file contains invalid JSON.
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The backing file contains invalid YAML.
+    #[cfg(feature = "yaml")]
+    #[error("invalid yaml: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    /// The changes could not be applied.
+    #[error("{0}")]
+    Apply(#[from] ApplyError),
+
+    /// A change targets a domain not permitted by the domain filter.
+    #[error("{0}")]
+    FilteredDomain(#[from] FilteredDomain),
+}
+
+impl ProviderError for FileError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            FileError::Io(_) | FileError::Json(_) => ErrorKind::Internal,
+            #[cfg(feature = "yaml")]
+            FileError::Yaml(_) => ErrorKind::Internal,
+            FileError::Apply(_) => ErrorKind::Conflict,
+            FileError::FilteredDomain(_) => ErrorKind::InvalidChanges,
+        }
+    }
+}
+
+/// Endpoints as of the last time the file was read or written.
+#[derive(Debug, Default)]
+struct Cache {
+    endpoints: EndpointSet,
+    stamp: Option<Stamp>,
+}
+
+/// Modification time and size of the backing file, used to detect external changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> io::Result<Option<Stamp>> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Some(Stamp {
+                modified: metadata.modified()?,
+                len: metadata.len(),
+            })),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl FileProvider {
+    /// Construct a provider backed by the file at the given path.
+    ///
+    /// The format is inferred using [`FileFormat::from_path`]. The file is
+    /// not read until the provider is first used.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        FileProvider {
+            format: FileFormat::from_path(&path),
+            path,
+            domain_filter: DomainFilter::default(),
+            cache: Arc::default(),
+        }
+    }
+
+    /// Override the format inferred from the path.
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Reject changes to domains outside of the given filter, before the
+    /// backing file is locked. [`Provider::init`] returns the filter as well.
+    pub fn with_domain_filter(mut self, domain_filter: DomainFilter) -> Self {
+        self.domain_filter = domain_filter;
+        self
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current endpoints held in the backing file.
+    pub async fn endpoints(&self) -> Result<Vec<Endpoint>, FileError> {
+        let provider = self.clone();
+        blocking(move || provider.read()).await
+    }
+
+    /// Open the lock file, creating it if necessary.
+    fn lock_file(&self) -> io::Result<File> {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(".lock");
+
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+    }
+
+    /// Reload the cached endpoints if the file has changed since it was last seen.
+    ///
+    /// Must be called while holding the file lock.
+    fn reload(&self, cache: &mut Cache) -> Result<(), FileError> {
+        let stamp = Stamp::of(&self.path)?;
+
+        if stamp == cache.stamp {
+            return Ok(());
+        }
+
+        debug!("reloading endpoints from {}", self.path.display());
+        cache.endpoints = match stamp {
+            Some(_) => EndpointSet::from(self.format.parse(&fs::read_to_string(&self.path)?)?),
+            None => EndpointSet::new(),
+        };
+        cache.stamp = stamp;
+
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<Endpoint>, FileError> {
+        let lock = self.lock_file()?;
+        FileExt::lock_shared(&lock)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        self.reload(&mut cache)?;
+
+        Ok(cache.endpoints.to_vec())
+    }
+
+    fn write(&self, changes: Vec<Change>) -> Result<(), FileError> {
+        let lock = self.lock_file()?;
+        FileExt::lock(&lock)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        self.reload(&mut cache)?;
+
+        // Apply to a copy, so the cache only changes once the file has been written.
+        let mut endpoints = cache.endpoints.clone();
+        endpoints.apply(changes)?;
+
+        let content = self.format.render(&endpoints.to_vec())?;
+        self.replace(content.as_bytes())?;
+
+        cache.endpoints = endpoints;
+        cache.stamp = Stamp::of(&self.path)?;
+
+        Ok(())
+    }
+
+    /// Atomically replace the contents of the backing file.
+    fn replace(&self, content: &[u8]) -> io::Result<()> {
+        let mut name = OsString::from(".");
+        name.push(self.path.file_name().unwrap_or_default());
+        name.push(".tmp");
+        let temporary = self.path.with_file_name(name);
+
+        let mut file = File::create(&temporary)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        drop(file);
+
+        if let Err(err) = fs::rename(&temporary, &self.path) {
+            let _ = fs::remove_file(&temporary);
+            return Err(err);
+        }
+
+        // Persist the rename itself. Directories cannot be opened as files on Windows.
+        #[cfg(unix)]
+        if let Some(parent) = self.path.parent().filter(|parent| parent != &Path::new("")) {
+            File::open(parent)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run blocking file operations outside of the async runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, FileError> + Send + 'static,
+) -> Result<T, FileError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[async_trait]
+impl Provider for FileProvider {
+    type Error = FileError;
+
+    async fn init(&self) -> Result<DomainFilter, Self::Error> {
+        Ok(self.domain_filter.clone())
+    }
+
+    async fn healthz(&self) -> Result<String, Self::Error> {
+        Ok("ok".to_string())
+    }
+
+    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
+        self.endpoints().await
+    }
+
+    #[instrument(skip(self))]
+    async fn set_records(&self, changes: Vec<Change>) -> Result<(), Self::Error> {
+        if let Some(dns_name) = self.domain_filter.rejected(&changes) {
+            return Err(FilteredDomain(dns_name.clone()).into());
+        }
+
+        let provider = self.clone();
+        blocking(move || provider.write(changes)).await
+    }
+
+    async fn adjust_endpoints(
+        &self,
+        endpoints: Vec<Endpoint>,
+    ) -> Result<Vec<Endpoint>, Self::Error> {
+        Ok(normalize_endpoints(endpoints))
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn file_persistence() {
+    use kubizone_common::Type;
+
+    let endpoint = |name: &str, target: &str| Endpoint {
+        record_ttl: Some(300),
+        ..Endpoint::test(name, Type::A, &[target])
+    };
+
+    let directory =
+        std::env::temp_dir().join(format!("external-dns-sdk-file-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    #[cfg(feature = "yaml")]
+    let path = directory.join("endpoints.yaml");
+    #[cfg(not(feature = "yaml"))]
+    let path = directory.join("endpoints.json");
+
+    let provider = FileProvider::new(&path);
+    assert_eq!(provider.get_records().await.unwrap(), vec![]);
+
+    provider
+        .set_records(vec![
+            Change::Create(endpoint("www.example.org.", "192.168.0.1")),
+            Change::Create(endpoint("api.example.org.", "192.168.0.2")),
+        ])
+        .await
+        .unwrap();
+
+    // Changes are visible to other providers backed by the same file.
+    let other = FileProvider::new(&path);
+    assert_eq!(
+        other.get_records().await.unwrap(),
+        vec![
+            endpoint("www.example.org.", "192.168.0.1"),
+            endpoint("api.example.org.", "192.168.0.2"),
+        ]
+    );
+
+    // Failed changes leave the file untouched.
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(matches!(
+        other
+            .set_records(vec![Change::Delete(endpoint(
+                "missing.example.org.",
+                "192.168.0.1"
+            ))])
+            .await,
+        Err(FileError::Apply(ApplyError::Missing(_)))
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    // External modifications are picked up.
+    fs::write(
+        &path,
+        FileFormat::from_path(&path)
+            .render(&[endpoint("external.example.org.", "192.168.0.3")])
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![endpoint("external.example.org.", "192.168.0.3")]
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,297 @@
+use kubizone_common::DomainName;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "provider")]
+use crate::Change;
+
+/// Domain filter returned by the provider during negotiation.
+///
+/// Mirrors the `DomainFilter` type of External-DNS, and is used to limit which
+/// domains External-DNS will attempt to manage through the webhook.
+///
+/// If either of the regular expressions are set, they take precedence over the
+/// plain `filters` and `exclude` lists, just like upstream.
+///
+/// Serialized using the field names of upstream's `domainFilterSerde`, that is
+/// `include`, `exclude`, `regexInclude` and `regexExclude`. As upstream only
+/// accepts one of the two modes, only the regular expressions are serialized
+/// if either of them is set, and deserializing a filter containing both a
+/// domain list and a regular expression fails. The `filters` name used by
+/// earlier versions of this crate is still accepted when deserializing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "DomainFilterSerde", try_from = "DomainFilterSerde")]
+pub struct DomainFilter {
+    /// Domains (and their subdomains) to include.
+    ///
+    /// A filter with a leading dot, such as `.example.org`, only matches
+    /// subdomains of `example.org`, but not the domain itself.
+    pub filters: Vec<String>,
+
+    /// Domains (and their subdomains) to exclude, even if included by `filters`.
+    pub exclude: Vec<String>,
+
+    /// Regular expression which domains must match to be included.
+    pub regex_filters: Option<Regex>,
+
+    /// Regular expression which excludes any domains matching it.
+    pub regex_exclusion: Option<Regex>,
+}
+
+impl DomainFilter {
+    /// Construct a filter including the given domains and their subdomains.
+    pub fn new<I, S>(filters: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        DomainFilter {
+            filters: filters.into_iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Exclude the given domains and their subdomains.
+    pub fn with_exclude<I, S>(mut self, exclude: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.exclude = exclude.into_iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Only include domains matching the given regular expression.
+    pub fn with_regex_filters(mut self, regex: Regex) -> Self {
+        self.regex_filters = Some(regex);
+        self
+    }
+
+    /// Exclude all domains matching the given regular expression.
+    pub fn with_regex_exclusion(mut self, regex: Regex) -> Self {
+        self.regex_exclusion = Some(regex);
+        self
+    }
+
+    /// Returns true if the domain is permitted by the filter.
+    ///
+    /// An empty filter matches all domains.
+    pub fn matches(&self, domain: &DomainName) -> bool {
+        let domain = normalize(&domain.to_string());
+
+        if self.regex_filters.is_some() || self.regex_exclusion.is_some() {
+            let included = match &self.regex_filters {
+                Some(regex) => regex.is_match(&domain),
+                None => true,
+            };
+
+            let excluded = self
+                .regex_exclusion
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(&domain));
+
+            return included && !excluded;
+        }
+
+        matches_any(&self.filters, &domain, true) && !matches_any(&self.exclude, &domain, false)
+    }
+
+    /// Find the first domain touched by the changes which is not permitted by the filter.
+    #[cfg(feature = "provider")]
+    pub(crate) fn rejected<'a>(&self, changes: &'a [Change]) -> Option<&'a DomainName> {
+        changes
+            .iter()
+            .flat_map(|change| match change {
+                Change::Update { old, new } => vec![old, new],
+                Change::Delete(endpoint) | Change::Create(endpoint) => vec![endpoint],
+            })
+            .map(|endpoint| &endpoint.identity.dns_name)
+            .find(|dns_name| !self.matches(dns_name))
+    }
+}
+
+impl From<Vec<DomainName>> for DomainFilter {
+    fn from(domains: Vec<DomainName>) -> Self {
+        DomainFilter::new(domains)
+    }
+}
+
+impl PartialEq for DomainFilter {
+    fn eq(&self, other: &Self) -> bool {
+        fn pattern(regex: &Option<Regex>) -> Option<&str> {
+            regex.as_ref().map(Regex::as_str)
+        }
+
+        self.filters == other.filters
+            && self.exclude == other.exclude
+            && pattern(&self.regex_filters) == pattern(&other.regex_filters)
+            && pattern(&self.regex_exclusion) == pattern(&other.regex_exclusion)
+    }
+}
+
+impl Eq for DomainFilter {}
+
+/// Port of upstream's `domainFilterSerde`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainFilterSerde {
+    #[serde(default, alias = "filters", skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    #[serde(default, with = "regex_serde", skip_serializing_if = "Option::is_none")]
+    regex_include: Option<Regex>,
+    #[serde(default, with = "regex_serde", skip_serializing_if = "Option::is_none")]
+    regex_exclude: Option<Regex>,
+}
+
+impl From<DomainFilter> for DomainFilterSerde {
+    fn from(filter: DomainFilter) -> Self {
+        if filter.regex_filters.is_some() || filter.regex_exclusion.is_some() {
+            return DomainFilterSerde {
+                include: Vec::new(),
+                exclude: Vec::new(),
+                regex_include: filter.regex_filters,
+                regex_exclude: filter.regex_exclusion,
+            };
+        }
+
+        DomainFilterSerde {
+            include: filter.filters,
+            exclude: filter.exclude,
+            regex_include: None,
+            regex_exclude: None,
+        }
+    }
+}
+
+impl TryFrom<DomainFilterSerde> for DomainFilter {
+    type Error = &'static str;
+
+    fn try_from(filter: DomainFilterSerde) -> Result<Self, Self::Error> {
+        let regex = filter.regex_include.is_some() || filter.regex_exclude.is_some();
+        let list = !filter.include.is_empty() || !filter.exclude.is_empty();
+
+        if regex && list {
+            return Err("cannot have both domain list and regex");
+        }
+
+        Ok(DomainFilter {
+            filters: filter.include,
+            exclude: filter.exclude,
+            regex_filters: filter.regex_include,
+            regex_exclusion: filter.regex_exclude,
+        })
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Port of upstream's `matchFilter`, returning `empty` if there are no filters.
+fn matches_any(filters: &[String], domain: &str, empty: bool) -> bool {
+    if filters.is_empty() {
+        return empty;
+    }
+
+    filters
+        .iter()
+        .map(|filter| normalize(filter.trim()))
+        .filter(|filter| !filter.is_empty())
+        .any(|filter| {
+            if filter.starts_with('.') {
+                domain.ends_with(&filter)
+            } else {
+                domain == filter || domain.ends_with(&format!(".{filter}"))
+            }
+        })
+}
+
+mod regex_serde {
+    use regex::Regex;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        regex: &Option<Regex>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match regex {
+            Some(regex) => serializer.serialize_str(regex.as_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Regex>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(pattern) if !pattern.is_empty() => {
+                Regex::new(&pattern).map(Some).map_err(D::Error::custom)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn domain_filter_matching() {
+    let domain = |name: &str| DomainName::try_from(name).unwrap();
+
+    assert!(DomainFilter::default().matches(&domain("example.org.")));
+
+    let filter = DomainFilter::new(["example.org"]).with_exclude(["internal.example.org"]);
+    assert!(filter.matches(&domain("example.org.")));
+    assert!(filter.matches(&domain("www.example.org")));
+    assert!(!filter.matches(&domain("example.com.")));
+    assert!(!filter.matches(&domain("notexample.org.")));
+    assert!(!filter.matches(&domain("db.internal.example.org.")));
+
+    let filter = DomainFilter::new([".example.org"]);
+    assert!(!filter.matches(&domain("example.org.")));
+    assert!(filter.matches(&domain("www.example.org.")));
+
+    let filter = DomainFilter::new(["example.com"])
+        .with_regex_filters(Regex::new(r"\.example\.org$").unwrap())
+        .with_regex_exclusion(Regex::new(r"^internal\.").unwrap());
+    assert!(!filter.matches(&domain("example.com.")));
+    assert!(filter.matches(&domain("www.example.org.")));
+    assert!(!filter.matches(&domain("internal.example.org.")));
+
+    let parsed: DomainFilter = serde_json::from_str(
+        r#"{"include":["example.com"],"exclude":["a.example.com"],"regexInclude":"","regexExclude":""}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        parsed,
+        DomainFilter::new(["example.com"]).with_exclude(["a.example.com"])
+    );
+    assert_eq!(
+        serde_json::to_string(&parsed).unwrap(),
+        r#"{"include":["example.com"],"exclude":["a.example.com"]}"#
+    );
+
+    let parsed: DomainFilter =
+        serde_json::from_str(r#"{"regexInclude":"\\.org$","regexExclude":"^b\\."}"#).unwrap();
+    assert_eq!(
+        parsed,
+        DomainFilter::default()
+            .with_regex_filters(Regex::new(r"\.org$").unwrap())
+            .with_regex_exclusion(Regex::new(r"^b\.").unwrap())
+    );
+
+    // Like upstream, only the regular expressions are serialized if set, and
+    // filters mixing both modes are rejected.
+    assert_eq!(
+        serde_json::to_string(&parsed.with_exclude(["a.example.com"])).unwrap(),
+        r#"{"regexInclude":"\\.org$","regexExclude":"^b\\."}"#
+    );
+    assert!(serde_json::from_str::<DomainFilter>(
+        r#"{"include":["example.com"],"regexExclude":"^b\\."}"#
+    )
+    .is_err());
+
+    let legacy: DomainFilter = serde_json::from_str(r#"{"filters":["example.com"]}"#).unwrap();
+    assert_eq!(legacy, DomainFilter::new(["example.com"]));
+}
@@ -4,21 +4,78 @@ mod client;
 #[cfg(feature = "client")]
 pub use client::{Client, Error};
 
+mod diff;
+pub use diff::{
+    normalize_endpoints, normalize_target, normalize_targets, targets_equivalent, DiffOptions,
+    EndpointDiff, Policy, UnknownPolicy, OWNER_LABEL,
+};
+
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "encryption")]
+pub use encryption::{DecryptionError, EncryptionKey, InvalidKeyLength};
+
+mod error;
+pub use error::{ErrorBody, ErrorKind};
+
+#[cfg(feature = "file")]
+mod file;
+#[cfg(feature = "file")]
+pub use file::{FileError, FileFormat, FileProvider};
+
+mod filter;
+pub use filter::DomainFilter;
+
+#[cfg(feature = "memory")]
+mod memory;
+#[cfg(feature = "memory")]
+pub use memory::{InMemoryError, InMemoryProvider};
+
+#[cfg(feature = "metrics")]
+mod metrics;
+
+mod plan;
+pub use plan::{Conflict, Plan, Summary};
+
 #[cfg(feature = "provider")]
 mod provider;
-use kubizone_common::{DomainName, Type};
 #[cfg(feature = "provider")]
-pub use provider::{serve, Provider};
+pub use provider::{serve, Provider, ProviderError, Server, ServerBuilder, ServerError};
 
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+#[cfg(feature = "provider")]
+mod record;
+#[cfg(feature = "provider")]
+pub use record::{FilteredDomain, Record, RecordStore, RecordStoreProvider};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
-struct DomainFilter {
-    #[serde(default)]
-    pub filters: Vec<String>,
-}
+mod registry;
+pub use registry::{
+    parse_labels, serialize_labels, RegistryError, TxtRegistry, ENCRYPTION_NONCE_LABEL, HERITAGE,
+    RECORD_TYPE_TEMPLATE, RESOURCE_LABEL,
+};
+
+mod rollback;
+pub use rollback::{PartialApplyError, Rollback};
+
+mod set;
+pub use set::{ApplyError, EndpointSet};
+
+mod zone;
+pub use zone::{Partition, Zones};
+
+mod zonefile;
+pub use zonefile::{ZoneFile, ZoneFileError};
+
+#[cfg(feature = "provider")]
+mod zonestore;
+#[cfg(feature = "provider")]
+pub use zonestore::{UnmatchedZone, ZoneStore, ZoneStoreProvider};
+
+use kubizone_common::{DomainName, Type};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
 /// Uniquely identifiable parts of an Endpoint.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -41,6 +98,7 @@ pub struct Endpoint {
 
     /// One or more "targets", that is the R-data values returned
     /// when this record is queried.
+    #[serde(default, deserialize_with = "nullable")]
     pub targets: Vec<String>,
 
     /// Time-To-Live.
@@ -49,11 +107,19 @@ pub struct Endpoint {
 
     /// One or more labels associated with the record, if
     /// supported by the underlying provider.
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
     pub labels: HashMap<String, String>,
 
     /// Provider-specific properties associated with the endpoint.
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Vec::is_empty"
+    )]
     pub provider_specific: Vec<ProviderSpecificProperty>,
 }
 
@@ -68,15 +134,33 @@ pub struct ProviderSpecificProperty {
     pub value: String,
 }
 
+/// Wire format of a set of changes.
+///
+/// Depending on the version of External-DNS, Go's encoding of `plan.Changes`
+/// may use PascalCase field names, omit empty lists, or encode them as `null`,
+/// all of which are accepted here.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Changes {
+    #[serde(default, alias = "Create", deserialize_with = "nullable")]
     pub create: Vec<Endpoint>,
+    #[serde(default, alias = "UpdateOld", deserialize_with = "nullable")]
     pub update_old: Vec<Endpoint>,
+    #[serde(default, alias = "UpdateNew", deserialize_with = "nullable")]
     pub update_new: Vec<Endpoint>,
+    #[serde(default, alias = "Delete", deserialize_with = "nullable")]
     pub delete: Vec<Endpoint>,
 }
 
+/// Deserializes `null` as the default value, like Go's `encoding/json` does for nil slices and maps.
+fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 /// Change to apply to the record set held by the provider.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Change {
@@ -94,30 +178,131 @@ pub enum Change {
     Create(Endpoint),
 }
 
-impl From<Changes> for Vec<Change> {
-    fn from(changes: Changes) -> Self {
+/// Produced when the `updateOld` and `updateNew` entries of a set of
+/// changes cannot be paired up.
+///
+/// Entries are paired by their identity and set identifier.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum PairingError {
+    /// An `updateOld` entry has no corresponding `updateNew` entry.
+    #[error("updateOld entry {0} has no matching updateNew entry")]
+    UnmatchedOld(EndpointName),
+
+    /// An `updateNew` entry has no corresponding `updateOld` entry.
+    #[error("updateNew entry {0} has no matching updateOld entry")]
+    UnmatchedNew(EndpointName),
+
+    /// Multiple `updateOld` entries share the same identity.
+    #[error("duplicate updateOld entry {0}")]
+    DuplicateOld(EndpointName),
+
+    /// Multiple `updateNew` entries share the same identity.
+    #[error("duplicate updateNew entry {0}")]
+    DuplicateNew(EndpointName),
+}
+
+/// Identity and set identifier of an endpoint, for use in error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EndpointName(EndpointIdent, Option<String>);
+
+impl From<&Endpoint> for EndpointName {
+    fn from(endpoint: &Endpoint) -> Self {
+        EndpointName(endpoint.identity.clone(), endpoint.set_identifier.clone())
+    }
+}
+
+impl Display for EndpointName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.0.dns_name, self.0.record_type)?;
+
+        if let Some(set_identifier) = &self.1 {
+            write!(f, " ({set_identifier})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a record type from its name, case-insensitively.
+pub(crate) fn parse_type(record_type: &str) -> Option<Type> {
+    use serde::de::value::{Error, StrDeserializer};
+
+    let record_type = record_type.to_ascii_uppercase();
+    Type::deserialize(StrDeserializer::<Error>::new(&record_type)).ok()
+}
+
+impl Endpoint {
+    /// Identity and set identifier, which together uniquely identify an endpoint.
+    pub(crate) fn key(&self) -> (&EndpointIdent, Option<&str>) {
+        (&self.identity, self.set_identifier.as_deref())
+    }
+}
+
+#[cfg(test)]
+impl Endpoint {
+    /// Endpoint without a set identifier, TTL, labels or provider-specific
+    /// properties, for use in tests.
+    pub(crate) fn test(name: &str, record_type: Type, targets: &[&str]) -> Self {
+        Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from(name).unwrap(),
+                record_type,
+            },
+            set_identifier: None,
+            targets: targets.iter().map(ToString::to_string).collect(),
+            record_ttl: None,
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<Changes> for Vec<Change> {
+    type Error = PairingError;
+
+    fn try_from(changes: Changes) -> Result<Self, Self::Error> {
         let mut out = Vec::new();
 
         for endpoint in changes.delete {
             out.push(Change::Delete(endpoint));
         }
 
-        for old in changes.update_old {
-            if let Some(new) = changes
-                .update_new
-                .iter()
-                .find(|new| new.identity == old.identity)
-                .cloned()
-            {
-                out.push(Change::Update { old, new })
+        let mut update_new = HashMap::new();
+        for new in &changes.update_new {
+            if update_new.insert(new.key(), new).is_some() {
+                return Err(PairingError::DuplicateNew(new.into()));
             }
         }
 
+        let mut seen = HashSet::new();
+        for old in &changes.update_old {
+            if !seen.insert(old.key()) {
+                return Err(PairingError::DuplicateOld(old.into()));
+            }
+
+            let Some(new) = update_new.remove(&old.key()) else {
+                return Err(PairingError::UnmatchedOld(old.into()));
+            };
+
+            out.push(Change::Update {
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+
+        if let Some(new) = changes
+            .update_new
+            .iter()
+            .find(|new| update_new.contains_key(&new.key()))
+        {
+            return Err(PairingError::UnmatchedNew(new.into()));
+        }
+
         for endpoint in changes.create {
             out.push(Change::Create(endpoint))
         }
 
-        out
+        Ok(out)
     }
 }
 
@@ -145,164 +330,101 @@ impl From<Vec<Change>> for Changes {
     }
 }
 
-/// Utility trait for computing a differential from two lists of endpoints.
-pub trait EndpointDiff {
-    /// Compare current (self) and desired (other) state, and compute a list of
-    /// changes to move from one state to the other.
-    ///
-    /// * Endpoints contained in `self` but not in `other` will yield a [`Change::Delete`].
-    /// * Endpoints contained in `other` but not in `self` will yield a [`Change::Create`].
-    /// * Endpoints contained in both `self` and `other` will yield a [`Change::Update`],
-    ///     *if* the entries are not identical.
-    fn difference(self, other: Self) -> Vec<Change>;
-}
-
-impl EndpointDiff for Vec<Endpoint> {
-    fn difference(self, other: Self) -> Vec<Change> {
-        let old: HashMap<EndpointIdent, Endpoint> = HashMap::from_iter(
-            self.into_iter()
-                .map(|endpoint| (endpoint.identity.clone(), endpoint)),
-        );
-        let new: HashMap<EndpointIdent, Endpoint> = HashMap::from_iter(
-            other
-                .into_iter()
-                .map(|endpoint| (endpoint.identity.clone(), endpoint)),
-        );
-
-        let old_keys: HashSet<_> = old.keys().collect();
-        let new_keys: HashSet<_> = new.keys().collect();
-
-        let creates = new_keys
-            .difference(&old_keys)
-            .filter_map(|identity| new.get(identity))
-            .cloned()
-            .map(Change::Create);
-
-        let deletes = old_keys
-            .difference(&new_keys)
-            .filter_map(|identity| old.get(identity))
-            .cloned()
-            .map(Change::Delete);
-
-        let updates = old_keys.intersection(&new_keys).filter_map(|identity| {
-            let old = old.get(identity)?.clone();
-            let new = new.get(identity)?.clone();
-
-            if old == new {
-                return None;
-            }
-
-            Some(Change::Update { old, new })
-        });
-
-        deletes.into_iter().chain(updates).chain(creates).collect()
-    }
-}
-
 #[cfg(test)]
 #[test]
-fn difference_calculation() {
-    let a = vec![
-        Endpoint {
-            identity: EndpointIdent {
-                dns_name: DomainName::try_from("update.org.").unwrap(),
-                record_type: Type::A,
-            },
-            set_identifier: None,
-            targets: vec!["192.168.0.1".to_string()],
-            record_ttl: Some(300),
-            labels: HashMap::default(),
-            provider_specific: Vec::new(),
-        },
-        Endpoint {
-            identity: EndpointIdent {
-                dns_name: DomainName::try_from("delete.org.").unwrap(),
-                record_type: Type::A,
-            },
-            set_identifier: None,
-            targets: vec!["192.168.0.1".to_string()],
-            record_ttl: Some(300),
-            labels: HashMap::default(),
-            provider_specific: Vec::new(),
+fn changes_deserialization() {
+    let endpoint = |name: &str, record_type: Type, target: &str| Endpoint {
+        record_ttl: Some(300),
+        labels: HashMap::from([("owner".to_string(), "default".to_string())]),
+        ..Endpoint::test(name, record_type, &[target])
+    };
+
+    let create = Change::Create(endpoint("new.example.org", Type::CNAME, "www.example.org"));
+    let full = vec![
+        Change::Delete(endpoint("old.example.org", Type::A, "192.168.0.3")),
+        Change::Update {
+            old: endpoint("www.example.org", Type::A, "192.168.0.1"),
+            new: endpoint("www.example.org", Type::A, "192.168.0.2"),
         },
+        create.clone(),
     ];
 
-    let b = vec![
-        Endpoint {
-            identity: EndpointIdent {
-                dns_name: DomainName::try_from("update.org.").unwrap(),
-                record_type: Type::A,
-            },
-            set_identifier: None,
-            targets: vec!["192.168.0.2".to_string()],
-            record_ttl: Some(300),
-            labels: HashMap::default(),
-            provider_specific: Vec::new(),
-        },
-        Endpoint {
-            identity: EndpointIdent {
-                dns_name: DomainName::try_from("create.org.").unwrap(),
-                record_type: Type::A,
-            },
-            set_identifier: None,
-            targets: vec!["192.168.0.1".to_string()],
-            record_ttl: Some(300),
-            labels: HashMap::default(),
-            provider_specific: Vec::new(),
-        },
-    ];
+    let parse = |fixture: &str| {
+        Vec::<Change>::try_from(serde_json::from_str::<Changes>(fixture).unwrap()).unwrap()
+    };
 
-    let changes = a.difference(b);
+    assert_eq!(
+        parse(include_str!("../tests/fixtures/changes/camel_case.json")),
+        full
+    );
+    assert_eq!(
+        parse(include_str!("../tests/fixtures/changes/pascal_case.json")),
+        full
+    );
+    assert_eq!(
+        parse(include_str!(
+            "../tests/fixtures/changes/camel_case_omitempty.json"
+        )),
+        vec![create.clone()]
+    );
+    assert_eq!(
+        parse(include_str!(
+            "../tests/fixtures/changes/pascal_case_null.json"
+        )),
+        vec![create]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn update_pairing() {
+    let endpoint = |set_identifier: &str, target: &str| Endpoint {
+        set_identifier: Some(set_identifier.to_string()),
+        ..Endpoint::test("weighted.example.org.", Type::A, &[target])
+    };
+
+    let changes = Changes {
+        create: vec![],
+        update_old: vec![endpoint("a", "192.168.0.1"), endpoint("b", "192.168.0.2")],
+        update_new: vec![endpoint("b", "192.168.0.4"), endpoint("a", "192.168.0.3")],
+        delete: vec![],
+    };
 
     assert_eq!(
-        changes,
+        Vec::<Change>::try_from(changes).unwrap(),
         vec![
-            Change::Delete(Endpoint {
-                identity: EndpointIdent {
-                    dns_name: DomainName::try_from("delete.org.").unwrap(),
-                    record_type: Type::A,
-                },
-                set_identifier: None,
-                targets: vec!["192.168.0.1".to_string()],
-                record_ttl: Some(300),
-                labels: HashMap::default(),
-                provider_specific: Vec::new(),
-            }),
             Change::Update {
-                old: Endpoint {
-                    identity: EndpointIdent {
-                        dns_name: DomainName::try_from("update.org.").unwrap(),
-                        record_type: Type::A,
-                    },
-                    set_identifier: None,
-                    targets: vec!["192.168.0.1".to_string()],
-                    record_ttl: Some(300),
-                    labels: HashMap::default(),
-                    provider_specific: Vec::new(),
-                },
-                new: Endpoint {
-                    identity: EndpointIdent {
-                        dns_name: DomainName::try_from("update.org.").unwrap(),
-                        record_type: Type::A,
-                    },
-                    set_identifier: None,
-                    targets: vec!["192.168.0.2".to_string()],
-                    record_ttl: Some(300),
-                    labels: HashMap::default(),
-                    provider_specific: Vec::new(),
-                }
+                old: endpoint("a", "192.168.0.1"),
+                new: endpoint("a", "192.168.0.3"),
+            },
+            Change::Update {
+                old: endpoint("b", "192.168.0.2"),
+                new: endpoint("b", "192.168.0.4"),
             },
-            Change::Create(Endpoint {
-                identity: EndpointIdent {
-                    dns_name: DomainName::try_from("create.org.").unwrap(),
-                    record_type: Type::A,
-                },
-                set_identifier: None,
-                targets: vec!["192.168.0.1".to_string()],
-                record_ttl: Some(300),
-                labels: HashMap::default(),
-                provider_specific: Vec::new(),
-            })
         ]
-    )
+    );
+
+    let orphaned = Changes {
+        create: vec![],
+        update_old: vec![endpoint("a", "192.168.0.1")],
+        update_new: vec![endpoint("b", "192.168.0.2")],
+        delete: vec![],
+    };
+
+    assert_eq!(
+        Vec::<Change>::try_from(orphaned).unwrap_err(),
+        PairingError::UnmatchedOld(EndpointName::from(&endpoint("a", "192.168.0.1")))
+    );
+
+    let duplicated = Changes {
+        create: vec![],
+        update_old: vec![endpoint("a", "192.168.0.1")],
+        update_new: vec![endpoint("a", "192.168.0.2"), endpoint("a", "192.168.0.3")],
+        delete: vec![],
+    };
+
+    assert_eq!(
+        Vec::<Change>::try_from(duplicated).unwrap_err().to_string(),
+        "duplicate updateNew entry weighted.example.org. A (a)"
+    );
 }
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use tracing::{instrument, trace};
+
+use crate::{
+    normalize_endpoints, ApplyError, Change, DomainFilter, Endpoint, EndpointSet, ErrorKind,
+    FilteredDomain, Provider, ProviderError,
+};
+
+/// In-memory reference implementation of a [`Provider`].
+///
+/// Useful for local development, and as a fake in integration tests.
+/// Cloning the provider yields a handle to the same set of endpoints,
+/// which makes it possible to inspect the state of a provider after
+/// handing it to a [`Server`](crate::Server).
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryProvider {
+    endpoints: Arc<RwLock<EndpointSet>>,
+    domain_filter: DomainFilter,
+}
+
+/// Error returned by the [`InMemoryProvider`].
+#[derive(Debug, thiserror::Error)]
+pub enum InMemoryError {
+    /// The changes could not be applied.
+    #[error("{0}")]
+    Apply(#[from] ApplyError),
+
+    /// A change targets a domain not permitted by the domain filter.
+    #[error("{0}")]
+    FilteredDomain(#[from] FilteredDomain),
+}
+
+impl ProviderError for InMemoryError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            InMemoryError::Apply(_) => ErrorKind::Conflict,
+            InMemoryError::FilteredDomain(_) => ErrorKind::InvalidChanges,
+        }
+    }
+}
+
+impl InMemoryProvider {
+    /// Construct an empty provider, which accepts all domains.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject changes to domains outside of the given filter, which is
+    /// also what [`Provider::init`] returns.
+    pub fn with_domain_filter(mut self, domain_filter: DomainFilter) -> Self {
+        self.domain_filter = domain_filter;
+        self
+    }
+
+    /// Populate the provider with the given endpoints.
+    pub fn with_endpoints(self, endpoints: Vec<Endpoint>) -> Self {
+        InMemoryProvider {
+            endpoints: Arc::new(RwLock::new(EndpointSet::from(endpoints))),
+            ..self
+        }
+    }
+
+    /// Current endpoints held by the provider.
+    pub async fn endpoints(&self) -> Vec<Endpoint> {
+        self.endpoints.read().await.to_vec()
+    }
+}
+
+#[async_trait]
+impl Provider for InMemoryProvider {
+    type Error = InMemoryError;
+
+    async fn init(&self) -> Result<DomainFilter, Self::Error> {
+        Ok(self.domain_filter.clone())
+    }
+
+    async fn healthz(&self) -> Result<String, Self::Error> {
+        Ok("ok".to_string())
+    }
+
+    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
+        Ok(self.endpoints().await)
+    }
+
+    #[instrument(skip(self))]
+    async fn set_records(&self, changes: Vec<Change>) -> Result<(), Self::Error> {
+        if let Some(dns_name) = self.domain_filter.rejected(&changes) {
+            return Err(FilteredDomain(dns_name.clone()).into());
+        }
+
+        trace!("applying {} changes", changes.len());
+        Ok(self.endpoints.write().await.apply(changes)?)
+    }
+
+    async fn adjust_endpoints(
+        &self,
+        endpoints: Vec<Endpoint>,
+    ) -> Result<Vec<Endpoint>, Self::Error> {
+        Ok(normalize_endpoints(endpoints))
+    }
+}
@@ -0,0 +1,150 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::Change;
+
+/// Prometheus metrics collected by the webhook server.
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+    changes: IntCounterVec,
+}
+
+impl Metrics {
+    /// Create the webhook metrics and register them with the given registry.
+    pub fn new(registry: Registry) -> Result<Self, prometheus::Error> {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "external_dns_webhook_requests_total",
+                "Number of requests handled by the webhook, by route, method and status code.",
+            ),
+            &["route", "method", "status"],
+        )?;
+
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "external_dns_webhook_provider_duration_seconds",
+                "Time spent in provider operations, in seconds.",
+            ),
+            &["operation"],
+        )?;
+
+        let changes = IntCounterVec::new(
+            Opts::new(
+                "external_dns_webhook_changes_total",
+                "Number of changes passed to the provider, by type of change.",
+            ),
+            &["change"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(duration.clone()))?;
+        registry.register(Box::new(changes.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            requests,
+            duration,
+            changes,
+        })
+    }
+
+    /// Record the duration of a provider operation.
+    pub fn observe_duration(&self, operation: &str, start: Instant) {
+        self.duration
+            .with_label_values(&[operation])
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    /// Count each change by its variant.
+    pub fn count_changes(&self, changes: &[Change]) {
+        for change in changes {
+            let kind = match change {
+                Change::Update { .. } => "update",
+                Change::Delete(_) => "delete",
+                Change::Create(_) => "create",
+            };
+
+            self.changes.with_label_values(&[kind]).inc();
+        }
+    }
+
+    /// Render all metrics in the registry in the Prometheus text format.
+    fn encode(&self) -> Result<Vec<u8>, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// Middleware counting requests by matched route, method and status code.
+pub(crate) async fn track(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    metrics
+        .requests
+        .with_label_values(&[&route, &method, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+/// Serves the `/metrics` endpoint.
+pub(crate) async fn handler(State(metrics): State<Arc<Metrics>>) -> Response {
+    match metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, TextEncoder::new().format_type().to_string())],
+            body,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn metrics_encoding() {
+    use crate::Endpoint;
+    use kubizone_common::Type;
+
+    let metrics = Metrics::new(Registry::new()).unwrap();
+
+    let endpoint = Endpoint::test("example.org.", Type::A, &["192.168.0.1"]);
+
+    metrics.count_changes(&[
+        Change::Create(endpoint.clone()),
+        Change::Delete(endpoint.clone()),
+        Change::Create(endpoint),
+    ]);
+    metrics.observe_duration("set_records", Instant::now());
+
+    let output = String::from_utf8(metrics.encode().unwrap()).unwrap();
+
+    assert!(output.contains(r#"external_dns_webhook_changes_total{change="create"} 2"#));
+    assert!(output.contains(r#"external_dns_webhook_changes_total{change="delete"} 1"#));
+    assert!(output.contains(
+        r#"external_dns_webhook_provider_duration_seconds_count{operation="set_records"} 1"#
+    ));
+}
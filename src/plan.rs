@@ -0,0 +1,274 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter, Result},
+};
+
+use kubizone_common::{DomainName, Type};
+
+use crate::{Change, Endpoint, EndpointName, OWNER_LABEL};
+
+/// Changes computed by [`EndpointDiff::plan`](crate::EndpointDiff::plan),
+/// along with any conflicts which prevented changes from being produced.
+///
+/// The [`Display`] implementation renders a human-readable overview of the
+/// plan, including field-level differences for each [`Change::Update`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Changes to apply.
+    pub changes: Vec<Change>,
+
+    /// Conflicts encountered while computing the changes.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Conflict between the current and desired state, which cannot be
+/// resolved by applying changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// The desired endpoint differs from the current one, but the current
+    /// endpoint is owned by someone else.
+    ForeignOwner {
+        /// Endpoint currently held by the provider.
+        current: Box<Endpoint>,
+        /// Endpoint which would have replaced it.
+        desired: Box<Endpoint>,
+    },
+
+    /// The desired state contains a CNAME record alongside records of other
+    /// types with the same name, which DNS does not permit.
+    ///
+    /// This is reported for information only, and does not prevent changes
+    /// from being produced.
+    CnameCoexistence {
+        /// Name of the CNAME record.
+        dns_name: DomainName,
+        /// Other record types present for the same name.
+        types: Vec<Type>,
+    },
+}
+
+/// Number of changes of each type in a [`Plan`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Summary {
+    /// Number of [`Change::Create`]s.
+    pub creates: usize,
+    /// Number of [`Change::Update`]s.
+    pub updates: usize,
+    /// Number of [`Change::Delete`]s.
+    pub deletes: usize,
+}
+
+impl Plan {
+    /// Count the changes of each type.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+
+        for change in &self.changes {
+            match change {
+                Change::Update { .. } => summary.updates += 1,
+                Change::Delete(_) => summary.deletes += 1,
+                Change::Create(_) => summary.creates += 1,
+            }
+        }
+
+        summary
+    }
+
+    /// Returns true if the plan contains neither changes nor conflicts.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+/// Find all names in the given state which hold a CNAME alongside other record types.
+pub(crate) fn cname_conflicts(endpoints: &[Endpoint]) -> Vec<Conflict> {
+    let mut types: BTreeMap<&DomainName, BTreeSet<Type>> = BTreeMap::new();
+
+    for endpoint in endpoints {
+        types
+            .entry(&endpoint.identity.dns_name)
+            .or_default()
+            .insert(endpoint.identity.record_type);
+    }
+
+    types
+        .into_iter()
+        .filter(|(_, types)| types.contains(&Type::CNAME) && types.len() > 1)
+        .map(|(dns_name, types)| Conflict::CnameCoexistence {
+            dns_name: dns_name.clone(),
+            types: types.into_iter().filter(|ty| *ty != Type::CNAME).collect(),
+        })
+        .collect()
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{} to create, {} to update, {} to delete",
+            self.creates, self.updates, self.deletes
+        )
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "Plan: {}.", self.summary())?;
+
+        for change in &self.changes {
+            writeln!(f)?;
+
+            match change {
+                Change::Create(endpoint) => {
+                    writeln!(f, "+ {}", EndpointName::from(endpoint))?;
+                    write_fields(f, endpoint)?;
+                }
+                Change::Delete(endpoint) => {
+                    writeln!(f, "- {}", EndpointName::from(endpoint))?;
+                    write_fields(f, endpoint)?;
+                }
+                Change::Update { old, new } => {
+                    writeln!(f, "~ {}", EndpointName::from(old))?;
+                    write_field_diff(f, old, new)?;
+                }
+            }
+        }
+
+        if !self.conflicts.is_empty() {
+            writeln!(f, "\nConflicts:")?;
+
+            for conflict in &self.conflicts {
+                writeln!(f, "! {conflict}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Conflict::ForeignOwner { current, .. } => {
+                write!(f, "{} is owned by ", EndpointName::from(current.as_ref()))?;
+
+                match current.labels.get(OWNER_LABEL) {
+                    Some(owner) => write!(f, "{owner:?}"),
+                    None => f.write_str("nobody"),
+                }
+            }
+            Conflict::CnameCoexistence { dns_name, types } => {
+                let types: Vec<String> = types.iter().map(ToString::to_string).collect();
+                write!(f, "{dns_name} CNAME coexists with {}", types.join(", "))
+            }
+        }
+    }
+}
+
+/// Renderable fields of an endpoint, by name.
+fn fields(endpoint: &Endpoint) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+
+    fields.insert("targets".to_string(), endpoint.targets.join(", "));
+
+    if let Some(ttl) = endpoint.record_ttl {
+        fields.insert("recordTTL".to_string(), ttl.to_string());
+    }
+
+    for (key, value) in &endpoint.labels {
+        fields.insert(format!("labels.{key}"), value.clone());
+    }
+
+    for property in &endpoint.provider_specific {
+        fields.insert(
+            format!("providerSpecific.{}", property.name),
+            property.value.clone(),
+        );
+    }
+
+    fields
+}
+
+fn write_fields(f: &mut Formatter<'_>, endpoint: &Endpoint) -> Result {
+    for (name, value) in fields(endpoint) {
+        writeln!(f, "    {name}: {value}")?;
+    }
+
+    Ok(())
+}
+
+fn write_field_diff(f: &mut Formatter<'_>, old: &Endpoint, new: &Endpoint) -> Result {
+    let old = fields(old);
+    let new = fields(new);
+
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    for name in names {
+        match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) if old == new => {}
+            (Some(old), Some(new)) => writeln!(f, "    {name}: {old} -> {new}")?,
+            (Some(old), None) => writeln!(f, "    {name}: {old} -> (none)")?,
+            (None, Some(new)) => writeln!(f, "    {name}: (none) -> {new}")?,
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn plan_rendering() {
+    use crate::{DiffOptions, EndpointDiff};
+
+    let endpoint = |name: &str, record_type: Type, target: &str, ttl: i64| Endpoint {
+        record_ttl: Some(ttl),
+        ..Endpoint::test(name, record_type, &[target])
+    };
+
+    let current = vec![
+        endpoint("update.org.", Type::A, "192.168.0.1", 300),
+        endpoint("delete.org.", Type::A, "192.168.0.1", 300),
+    ];
+    let desired = vec![
+        endpoint("update.org.", Type::A, "192.168.0.2", 600),
+        endpoint("www.org.", Type::CNAME, "update.org.", 300),
+        endpoint("www.org.", Type::A, "192.168.0.3", 300),
+    ];
+
+    let plan = current.plan(desired, &DiffOptions::default());
+
+    assert_eq!(
+        plan.summary(),
+        Summary {
+            creates: 2,
+            updates: 1,
+            deletes: 1
+        }
+    );
+
+    assert_eq!(
+        plan.to_string(),
+        "Plan: 2 to create, 1 to update, 1 to delete.
+
+- delete.org. A
+    recordTTL: 300
+    targets: 192.168.0.1
+
+~ update.org. A
+    recordTTL: 300 -> 600
+    targets: 192.168.0.1 -> 192.168.0.2
+
++ www.org. CNAME
+    recordTTL: 300
+    targets: update.org.
+
++ www.org. A
+    recordTTL: 300
+    targets: 192.168.0.3
+
+Conflicts:
+! www.org. CNAME coexists with A
+"
+    );
+}
@@ -1,29 +1,47 @@
-use std::{fmt::Display, net::SocketAddr, sync::Arc};
+use std::{
+    fmt::Display,
+    future::Future,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use axum::{
     extract::State,
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER, VARY},
+        HeaderValue,
+    },
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
-use kubizone_common::DomainName;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::watch};
 use tracing::{info_span, warn};
 
-use crate::{Change, Changes, Endpoint};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Metrics};
+use crate::{
+    ApplyError, Change, Changes, DomainFilter, Endpoint, ErrorBody, ErrorKind, PairingError,
+    PartialApplyError,
+};
+
+/// Media type used for negotiating the webhook API version with External-DNS.
+const MEDIA_TYPE: &str = "application/external.dns.webhook+json;version=1";
 
 /// Utility trait for implementing an external-dns webhook provider.
 ///
 /// By implementing this trait for your type, you can simply construct
-/// it and pass it to [`serve`], and you're set.
+/// it and pass it to [`serve`] or [`Server::builder`], and you're set.
 ///
-/// See an example in-memory imlpementation in the `e2e.rs` test example.
+/// See `InMemoryProvider` (behind the `memory` feature) for an example implementation.
 #[async_trait]
 pub trait Provider {
-    type Error: Display;
+    type Error: ProviderError;
     /// Initialisation and negotiates headers and returns domain filter.
-    async fn init(&self) -> Result<Vec<DomainName>, Self::Error>;
+    async fn init(&self) -> Result<DomainFilter, Self::Error>;
 
     /// Health check
     ///
@@ -45,58 +63,404 @@ pub trait Provider {
     ) -> Result<Vec<Endpoint>, Self::Error>;
 }
 
+/// Error returned by a [`Provider`].
+///
+/// Lets the error declare which [`ErrorKind`] it represents, which in turn
+/// decides the status code returned by the webhook server. All methods have
+/// defaults, so an empty `impl ProviderError for MyError {}` yields a
+/// plain-text `500 Internal Server Error`, like any other error.
+pub trait ProviderError: Display {
+    /// Category of the error. Defaults to [`ErrorKind::Internal`].
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Internal
+    }
+
+    /// Delay after which the request may be retried, sent as a `Retry-After` header.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Respond with a JSON [`ErrorBody`] rather than a plain-text message.
+    ///
+    /// The [`Client`](crate::Client) decodes these into [`Error::Provider`](crate::Error::Provider).
+    fn structured(&self) -> bool {
+        false
+    }
+}
+
+impl ProviderError for &'static str {}
+impl ProviderError for String {}
+impl ProviderError for std::io::Error {}
+impl ProviderError for Box<dyn std::error::Error + Send + Sync> {}
+
+impl ProviderError for PairingError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::InvalidChanges
+    }
+}
+
+impl ProviderError for ApplyError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Conflict
+    }
+}
+
+impl<E: ProviderError> ProviderError for PartialApplyError<E> {
+    fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        self.error.retry_after()
+    }
+
+    fn structured(&self) -> bool {
+        self.error.structured()
+    }
+}
+
 struct Context<P: Provider>
 where
     Arc<P>:,
 {
     provider: Arc<P>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
 }
 
 impl<P: Provider> Clone for Context<P> {
     fn clone(&self) -> Self {
         Self {
             provider: self.provider.clone(),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
         }
     }
 }
 
+impl<P: Provider> Context<P> {
+    /// Await the given provider operation, recording its duration if metrics are enabled.
+    async fn timed<F: Future>(&self, _operation: &str, operation: F) -> F::Output {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        let output = operation.await;
+
+        #[cfg(feature = "metrics")]
+        self.metrics.observe_duration(_operation, start);
+
+        output
+    }
+}
+
+type ShutdownSignal = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Produced when the webhook server fails to bind or serve.
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    /// Failed to bind the listener to the given address.
+    #[error("failed to bind {0}: {1}")]
+    Bind(SocketAddr, std::io::Error),
+
+    /// Failed to determine the local address of the listener.
+    #[error("failed to determine local address: {0}")]
+    LocalAddr(std::io::Error),
+
+    /// Failure while serving requests.
+    #[error("serve: {0}")]
+    Serve(std::io::Error),
+
+    /// Failed to register metrics.
+    #[cfg(feature = "metrics")]
+    #[error("metrics: {0}")]
+    Metrics(#[from] prometheus::Error),
+}
+
+/// External-DNS compatible webhook server.
+///
+/// Constructed using [`Server::builder`], and run using [`Server::run`].
+///
+/// By default the webhook API and health check are served from a single
+/// listener. If a separate health listener is configured using
+/// [`ServerBuilder::health_bind`], `/healthz` is additionally served on it,
+/// which allows the API itself to only be exposed on localhost, while
+/// Kubernetes probes can still reach the health check over the pod network.
+/// In that case `/metrics` is only served on the health listener.
+pub struct Server {
+    listener: TcpListener,
+    router: Router,
+    health: Option<(TcpListener, Router)>,
+    shutdown: ShutdownSignal,
+}
+
+/// Builder for configuring a webhook [`Server`].
+pub struct ServerBuilder<P> {
+    provider: P,
+    addr: SocketAddr,
+    listener: Option<TcpListener>,
+    health_addr: Option<SocketAddr>,
+    health_listener: Option<TcpListener>,
+    shutdown: Option<ShutdownSignal>,
+    #[cfg(feature = "metrics")]
+    registry: Option<prometheus::Registry>,
+}
+
+impl Server {
+    /// Default address of the webhook API, as expected by External-DNS.
+    pub const DEFAULT_ADDR: SocketAddr =
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8888));
+
+    /// Default address of the health listener used by upstream webhook providers.
+    pub const DEFAULT_HEALTH_ADDR: SocketAddr =
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080));
+
+    /// Start configuring a server for the given provider.
+    pub fn builder<P: Provider + Send + Sync + 'static>(provider: P) -> ServerBuilder<P> {
+        ServerBuilder {
+            provider,
+            addr: Self::DEFAULT_ADDR,
+            listener: None,
+            health_addr: None,
+            health_listener: None,
+            shutdown: None,
+            #[cfg(feature = "metrics")]
+            registry: None,
+        }
+    }
+
+    /// Address the server is listening on.
+    ///
+    /// Useful for discovering the assigned port, when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, ServerError> {
+        self.listener.local_addr().map_err(ServerError::LocalAddr)
+    }
+
+    /// Address the separate health listener is listening on, if configured.
+    pub fn health_local_addr(&self) -> Result<Option<SocketAddr>, ServerError> {
+        self.health
+            .as_ref()
+            .map(|(listener, _)| listener.local_addr().map_err(ServerError::LocalAddr))
+            .transpose()
+    }
+
+    /// Serve requests until the shutdown signal fires.
+    pub async fn run(self) -> Result<(), ServerError> {
+        info_span!("external-dns-sdk");
+
+        let Some((health_listener, health_router)) = self.health else {
+            return axum::serve(self.listener, self.router.into_make_service())
+                .with_graceful_shutdown(self.shutdown)
+                .await
+                .map_err(ServerError::Serve);
+        };
+
+        // Fan the single shutdown signal out to both listeners.
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let wait_for_shutdown = |mut receiver: watch::Receiver<bool>| async move {
+            receiver.wait_for(|shutdown| *shutdown).await.ok();
+        };
+
+        let api = axum::serve(self.listener, self.router.into_make_service())
+            .with_graceful_shutdown(wait_for_shutdown(shutdown_rx.clone()));
+
+        let health = axum::serve(health_listener, health_router.into_make_service())
+            .with_graceful_shutdown(wait_for_shutdown(shutdown_rx));
+
+        let shutdown = async move {
+            self.shutdown.await;
+            shutdown_tx.send_replace(true);
+            Ok(())
+        };
+
+        tokio::try_join!(
+            async { api.await.map_err(ServerError::Serve) },
+            async { health.await.map_err(ServerError::Serve) },
+            shutdown,
+        )
+        .map(|_| ())
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static> ServerBuilder<P> {
+    /// Address to bind to. Defaults to [`Server::DEFAULT_ADDR`].
+    ///
+    /// Use port 0 to have the operating system assign a free port,
+    /// which can then be retrieved using [`Server::local_addr`].
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Use an already bound listener, instead of binding a new one.
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Serve the health check on a separate listener bound to the given address.
+    ///
+    /// Upstream webhook providers use [`Server::DEFAULT_HEALTH_ADDR`] for this.
+    pub fn health_bind(mut self, addr: SocketAddr) -> Self {
+        self.health_addr = Some(addr);
+        self
+    }
+
+    /// Serve the health check on an already bound listener.
+    pub fn health_listener(mut self, listener: TcpListener) -> Self {
+        self.health_listener = Some(listener);
+        self
+    }
+
+    /// Register metrics with the given registry, instead of a new one.
+    ///
+    /// Useful when embedding the webhook in a binary which already exposes metrics.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_registry(mut self, registry: prometheus::Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Shut the server down gracefully once the given future completes.
+    ///
+    /// This replaces the default Ctrl+C/SIGTERM handler. To shut down using
+    /// a cancellation token, pass `token.cancelled_owned()`.
+    pub fn with_graceful_shutdown<F>(mut self, signal: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown = Some(Box::pin(signal));
+        self
+    }
+
+    /// Bind the listener and construct the server.
+    pub async fn build(self) -> Result<Server, ServerError> {
+        let listener = match self.listener {
+            Some(listener) => listener,
+            None => TcpListener::bind(self.addr)
+                .await
+                .map_err(|err| ServerError::Bind(self.addr, err))?,
+        };
+
+        let health = match (self.health_listener, self.health_addr) {
+            (Some(listener), _) => Some(listener),
+            (None, Some(addr)) => Some(
+                TcpListener::bind(addr)
+                    .await
+                    .map_err(|err| ServerError::Bind(addr, err))?,
+            ),
+            (None, None) => None,
+        };
+
+        let context = Context {
+            provider: Arc::new(self.provider),
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Metrics::new(self.registry.unwrap_or_default())?),
+        };
+
+        Ok(Server {
+            listener,
+            router: api_router(context.clone(), health.is_none()),
+            health: health.map(|listener| (listener, health_router(context))),
+            shutdown: self.shutdown.unwrap_or_else(|| Box::pin(shutdown_signal())),
+        })
+    }
+}
+
 /// Run an External-DNS compatible webhook provider, using an Axum server.
-pub async fn serve<P: Provider + Send + Sync + 'static>(addr: SocketAddr, provider: P) {
-    info_span!("external-dns-sdk");
-    let app = Router::new()
+///
+/// Shuts down gracefully on Ctrl+C or SIGTERM. See [`Server::builder`] for
+/// more control over how the server is run.
+pub async fn serve<P: Provider + Send + Sync + 'static>(
+    addr: SocketAddr,
+    provider: P,
+) -> Result<(), ServerError> {
+    Server::builder(provider)
+        .bind(addr)
+        .build()
+        .await?
+        .run()
+        .await
+}
+
+fn api_router<P: Provider + Send + Sync + 'static>(
+    context: Context<P>,
+    serve_metrics: bool,
+) -> Router {
+    let router = Router::new()
+        .route("/", get(init::<P>))
         .route("/healthz", get(healthz::<P>))
         .route("/records", get(get_records::<P>).post(set_records::<P>))
-        .route("/adjustendpoints", post(adjust_endpoints::<P>))
-        .with_state(Context {
-            provider: Arc::new(provider),
-        });
+        .route("/adjustendpoints", post(adjust_endpoints::<P>));
 
-    let listener = TcpListener::bind(addr).await.unwrap();
+    instrument(router, &context, serve_metrics).with_state(context)
+}
 
-    axum::serve(listener, app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+fn health_router<P: Provider + Send + Sync + 'static>(context: Context<P>) -> Router {
+    let router = Router::new().route("/healthz", get(healthz::<P>));
+
+    instrument(router, &context, true).with_state(context)
+}
+
+/// Count requests to all routes, serving `/metrics` as well if `serve_metrics` is set.
+#[cfg(feature = "metrics")]
+fn instrument<P: Provider + Send + Sync + 'static>(
+    router: Router<Context<P>>,
+    context: &Context<P>,
+    serve_metrics: bool,
+) -> Router<Context<P>> {
+    let router = if serve_metrics {
+        router.route(
+            "/metrics",
+            get(metrics::handler).with_state(context.metrics.clone()),
+        )
+    } else {
+        router
+    };
+
+    router.route_layer(axum::middleware::from_fn_with_state(
+        context.metrics.clone(),
+        metrics::track,
+    ))
+}
+
+#[cfg(not(feature = "metrics"))]
+fn instrument<P: Provider>(
+    router: Router<Context<P>>,
+    _: &Context<P>,
+    _: bool,
+) -> Router<Context<P>> {
+    router
+}
+
+async fn init<P: Provider>(State(context): State<Context<P>>) -> Response {
+    match context.provider.init().await {
+        Ok(filter) => (
+            axum::http::StatusCode::OK,
+            [(CONTENT_TYPE, MEDIA_TYPE), (VARY, "Content-Type")],
+            Json(filter),
+        )
+            .into_response(),
+        Err(err) => {
+            warn!("provider failed to initialize: {err}");
+            error_response(&err)
+        }
+    }
 }
 
-async fn healthz<P: Provider>(State(context): State<Context<P>>) -> impl IntoResponse {
+async fn healthz<P: Provider>(State(context): State<Context<P>>) -> Response {
     match context.provider.healthz().await {
-        Ok(result) => (axum::http::StatusCode::OK, result),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        ),
+        Ok(result) => (axum::http::StatusCode::OK, result).into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
 async fn get_records<P: Provider>(State(context): State<Context<P>>) -> Response {
-    match context.provider.get_records().await {
+    match context
+        .timed("get_records", context.provider.get_records())
+        .await
+    {
         Ok(result) => (axum::http::StatusCode::OK, Json(result)).into_response(),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
@@ -104,15 +468,23 @@ async fn set_records<P: Provider>(
     State(context): State<Context<P>>,
     Json(changes): Json<Changes>,
 ) -> Response {
-    let changes = Vec::<Change>::from(changes);
+    let changes = match Vec::<Change>::try_from(changes) {
+        Ok(changes) => changes,
+        Err(err) => {
+            warn!("rejecting changes: {err}");
+            return error_response(&err);
+        }
+    };
+
+    #[cfg(feature = "metrics")]
+    context.metrics.count_changes(&changes);
 
-    match context.provider.set_records(changes).await {
+    match context
+        .timed("set_records", context.provider.set_records(changes))
+        .await
+    {
         Ok(result) => (axum::http::StatusCode::OK, Json(result)).into_response(),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
@@ -120,14 +492,48 @@ async fn adjust_endpoints<P: Provider>(
     State(context): State<Context<P>>,
     Json(endpoints): Json<Vec<Endpoint>>,
 ) -> Response {
-    match context.provider.adjust_endpoints(endpoints).await {
+    match context
+        .timed(
+            "adjust_endpoints",
+            context.provider.adjust_endpoints(endpoints),
+        )
+        .await
+    {
         Ok(result) => (axum::http::StatusCode::OK, Json(result)).into_response(),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// Convert a provider error into a response with the appropriate status code.
+fn error_response<E: ProviderError>(err: &E) -> Response {
+    let kind = err.kind();
+    let status = axum::http::StatusCode::from_u16(kind.status_code())
+        .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let retry_after = err
+        .retry_after()
+        .map(|delay| delay.as_secs_f64().ceil() as u64);
+
+    let mut response = if err.structured() {
+        (
+            status,
+            Json(ErrorBody {
+                kind,
+                message: err.to_string(),
+                retry_after,
+            }),
         )
-            .into_response(),
+            .into_response()
+    } else {
+        (status, err.to_string()).into_response()
+    };
+
+    if let Some(retry_after) = retry_after {
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
     }
+
+    response
 }
 
 async fn shutdown_signal() {
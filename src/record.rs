@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use kubizone_common::DomainName;
+use tracing::{instrument, trace};
+
+use crate::{
+    normalize_target, Change, DomainFilter, Endpoint, EndpointIdent, ErrorKind, PartialApplyError,
+    Provider, ProviderError,
+};
+
+/// Single resource record, corresponding to one target of an [`Endpoint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Name and type of the record.
+    pub identity: EndpointIdent,
+
+    /// Set identifier of the endpoint the record belongs to, if any.
+    pub set_identifier: Option<String>,
+
+    /// Value of the record.
+    pub target: String,
+
+    /// Time-To-Live.
+    pub ttl: Option<i64>,
+}
+
+/// Backend which manages DNS records one value at a time.
+///
+/// Many DNS APIs expose create, update and delete operations for individual
+/// record values rather than entire record sets. Implementing this trait and
+/// wrapping the backend in a [`RecordStoreProvider`] turns it into a
+/// [`Provider`], which takes care of mapping between records and endpoints.
+#[async_trait]
+pub trait RecordStore: Send + Sync {
+    /// Backend-specific identifier of a record.
+    type Id: Clone + Send + Sync;
+
+    /// Error returned by the store, which must also be able to represent
+    /// changes rejected by the [`RecordStoreProvider`]'s domain filter.
+    type Error: ProviderError + Send + From<FilteredDomain>;
+
+    /// List all records, along with their identifiers.
+    async fn list(&self) -> Result<Vec<(Self::Id, Record)>, Self::Error>;
+
+    /// Create a new record, returning its identifier.
+    async fn create_record(&self, record: Record) -> Result<Self::Id, Self::Error>;
+
+    /// Delete the record with the given identifier.
+    async fn delete_record(&self, id: Self::Id) -> Result<(), Self::Error>;
+
+    /// Replace the record with the given identifier.
+    async fn update_record(&self, id: Self::Id, record: Record) -> Result<(), Self::Error>;
+}
+
+/// Produced by providers such as the [`RecordStoreProvider`] when a change
+/// targets a domain not permitted by their domain filter.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("domain {0} is not permitted by the domain filter")]
+pub struct FilteredDomain(pub DomainName);
+
+impl ProviderError for FilteredDomain {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::InvalidChanges
+    }
+}
+
+/// [`Provider`] backed by a [`RecordStore`].
+///
+/// Records sharing a name, type and set identifier are aggregated into a
+/// single endpoint, taking the TTL of the first record. Records carry
+/// neither labels nor provider-specific properties, so endpoints returned
+/// by the provider never have any.
+///
+/// Changes are expanded into per-record operations. Updates only touch the
+/// records whose targets or TTL actually change, and replace removed targets
+/// with added ones in-place where possible. Changes to domains not permitted
+/// by the domain filter are rejected with a [`FilteredDomain`] error, before
+/// any of the changes are applied.
+///
+/// Changes are applied in order, so if the store fails part of the way
+/// through a batch, the returned [`PartialApplyError`] holds the index of
+/// the failed change, which can be used to roll back the preceding ones.
+#[derive(Debug, Clone)]
+pub struct RecordStoreProvider<S> {
+    store: S,
+    domain_filter: DomainFilter,
+}
+
+type Key = (EndpointIdent, Option<String>);
+
+impl<S: RecordStore> RecordStoreProvider<S> {
+    /// Wrap the given store.
+    pub fn new(store: S) -> Self {
+        RecordStoreProvider {
+            store,
+            domain_filter: DomainFilter::default(),
+        }
+    }
+
+    /// Reject changes to domains outside of the given filter, before any
+    /// records are touched. Also returned from [`Provider::init`].
+    pub fn with_domain_filter(mut self, domain_filter: DomainFilter) -> Self {
+        self.domain_filter = domain_filter;
+        self
+    }
+
+    /// The wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Create a record for every target of the endpoint.
+    async fn create(
+        &self,
+        existing: &mut HashMap<Key, Vec<(S::Id, Record)>>,
+        endpoint: Endpoint,
+    ) -> Result<(), S::Error> {
+        let records = existing.entry(key(&endpoint)).or_default();
+
+        for target in &endpoint.targets {
+            let record = record(&endpoint, target);
+            let id = self.store.create_record(record.clone()).await?;
+            records.push((id, record));
+        }
+
+        Ok(())
+    }
+
+    /// Delete the existing records matching the targets of the endpoint.
+    ///
+    /// Targets without a matching record are assumed to be deleted already.
+    async fn delete(
+        &self,
+        existing: &mut HashMap<Key, Vec<(S::Id, Record)>>,
+        endpoint: &Endpoint,
+    ) -> Result<(), S::Error> {
+        let records = existing.remove(&key(endpoint)).unwrap_or_default();
+        let record_type = endpoint.identity.record_type;
+        let mut remaining = Vec::new();
+
+        for (id, record) in records {
+            if contains(record_type, &endpoint.targets, &record.target) {
+                self.store.delete_record(id).await?;
+            } else {
+                remaining.push((id, record));
+            }
+        }
+
+        if !remaining.is_empty() {
+            existing.insert(key(endpoint), remaining);
+        }
+
+        Ok(())
+    }
+
+    /// Apply the target-level difference between two endpoints.
+    async fn update(
+        &self,
+        existing: &mut HashMap<Key, Vec<(S::Id, Record)>>,
+        old: Endpoint,
+        new: Endpoint,
+    ) -> Result<(), S::Error> {
+        if key(&old) != key(&new) {
+            self.delete(existing, &old).await?;
+            return self.create(existing, new).await;
+        }
+
+        let record_type = new.identity.record_type;
+        let records = existing.remove(&key(&old)).unwrap_or_default();
+
+        let (kept, mut removed): (Vec<_>, Vec<_>) = records
+            .into_iter()
+            .partition(|(_, record)| contains(record_type, &new.targets, &record.target));
+
+        let added: Vec<&String> = new
+            .targets
+            .iter()
+            .filter(|target| {
+                let target = normalize_target(record_type, target);
+                !kept
+                    .iter()
+                    .any(|(_, record)| normalize_target(record_type, &record.target) == target)
+            })
+            .collect();
+
+        let mut records = Vec::with_capacity(new.targets.len());
+
+        for (id, record) in kept {
+            if record.ttl == new.record_ttl {
+                records.push((id, record));
+                continue;
+            }
+
+            let record = self::record(&new, &record.target);
+            self.store.update_record(id.clone(), record.clone()).await?;
+            records.push((id, record));
+        }
+
+        for target in added {
+            let record = record(&new, target);
+
+            let id = match removed.pop() {
+                Some((id, _)) => {
+                    self.store.update_record(id.clone(), record.clone()).await?;
+                    id
+                }
+                None => self.store.create_record(record.clone()).await?,
+            };
+
+            records.push((id, record));
+        }
+
+        for (id, _) in removed {
+            self.store.delete_record(id).await?;
+        }
+
+        existing.insert(key(&new), records);
+        Ok(())
+    }
+}
+
+fn key(endpoint: &Endpoint) -> Key {
+    (endpoint.identity.clone(), endpoint.set_identifier.clone())
+}
+
+fn record(endpoint: &Endpoint, target: &str) -> Record {
+    Record {
+        identity: endpoint.identity.clone(),
+        set_identifier: endpoint.set_identifier.clone(),
+        target: target.to_string(),
+        ttl: endpoint.record_ttl,
+    }
+}
+
+/// Returns true if the targets contain one equivalent to `target`.
+fn contains(record_type: kubizone_common::Type, targets: &[String], target: &str) -> bool {
+    let target = normalize_target(record_type, target);
+
+    targets
+        .iter()
+        .any(|candidate| normalize_target(record_type, candidate) == target)
+}
+
+#[async_trait]
+impl<S: RecordStore> Provider for RecordStoreProvider<S> {
+    type Error = PartialApplyError<S::Error>;
+
+    async fn init(&self) -> Result<DomainFilter, Self::Error> {
+        Ok(self.domain_filter.clone())
+    }
+
+    async fn healthz(&self) -> Result<String, Self::Error> {
+        Ok("ok".to_string())
+    }
+
+    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
+        let mut endpoints: Vec<Endpoint> = Vec::new();
+        let mut index: HashMap<Key, usize> = HashMap::new();
+
+        for (_, record) in self.store.list().await? {
+            let key = (record.identity.clone(), record.set_identifier.clone());
+
+            match index.get(&key) {
+                Some(position) => endpoints[*position].targets.push(record.target),
+                None => {
+                    index.insert(key, endpoints.len());
+                    endpoints.push(Endpoint {
+                        identity: record.identity,
+                        set_identifier: record.set_identifier,
+                        targets: vec![record.target],
+                        record_ttl: record.ttl,
+                        labels: HashMap::default(),
+                        provider_specific: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        Ok(endpoints)
+    }
+
+    #[instrument(skip(self))]
+    async fn set_records(&self, changes: Vec<Change>) -> Result<(), Self::Error> {
+        if let Some(dns_name) = self.domain_filter.rejected(&changes) {
+            return Err(S::Error::from(FilteredDomain(dns_name.clone())).into());
+        }
+
+        let mut existing: HashMap<Key, Vec<(S::Id, Record)>> = HashMap::new();
+
+        for (id, record) in self.store.list().await? {
+            existing
+                .entry((record.identity.clone(), record.set_identifier.clone()))
+                .or_default()
+                .push((id, record));
+        }
+
+        trace!("applying {} changes", changes.len());
+        for (index, change) in changes.into_iter().enumerate() {
+            match change {
+                Change::Create(endpoint) => self.create(&mut existing, endpoint).await,
+                Change::Delete(endpoint) => self.delete(&mut existing, &endpoint).await,
+                Change::Update { old, new } => self.update(&mut existing, old, new).await,
+            }
+            .map_err(|error| PartialApplyError {
+                failed: Some(index),
+                error,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn adjust_endpoints(
+        &self,
+        endpoints: Vec<Endpoint>,
+    ) -> Result<Vec<Endpoint>, Self::Error> {
+        Ok(endpoints)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn record_store_provider() {
+    use crate::Rollback;
+    use kubizone_common::Type;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Operation {
+        Create(String),
+        Delete(u64),
+        Update(u64, String, Option<i64>),
+    }
+
+    #[derive(Default)]
+    struct Store {
+        next_id: Mutex<u64>,
+        records: Mutex<Vec<(u64, Record)>>,
+        operations: Mutex<Vec<Operation>>,
+    }
+
+    #[async_trait]
+    impl RecordStore for Store {
+        type Id = u64;
+        type Error = FilteredDomain;
+
+        async fn list(&self) -> Result<Vec<(u64, Record)>, FilteredDomain> {
+            Ok(self.records.lock().unwrap().clone())
+        }
+
+        async fn create_record(&self, record: Record) -> Result<u64, FilteredDomain> {
+            // Simulate the backend refusing a record the filter permits.
+            if record.identity.dns_name.to_string().starts_with("fail.") {
+                return Err(FilteredDomain(record.identity.dns_name));
+            }
+
+            let mut id = self.next_id.lock().unwrap();
+            *id += 1;
+            self.operations
+                .lock()
+                .unwrap()
+                .push(Operation::Create(record.target.clone()));
+            self.records.lock().unwrap().push((*id, record));
+            Ok(*id)
+        }
+
+        async fn delete_record(&self, id: u64) -> Result<(), FilteredDomain> {
+            self.records.lock().unwrap().retain(|(i, _)| *i != id);
+            self.operations.lock().unwrap().push(Operation::Delete(id));
+            Ok(())
+        }
+
+        async fn update_record(&self, id: u64, record: Record) -> Result<(), FilteredDomain> {
+            self.operations.lock().unwrap().push(Operation::Update(
+                id,
+                record.target.clone(),
+                record.ttl,
+            ));
+            for (i, existing) in self.records.lock().unwrap().iter_mut() {
+                if *i == id {
+                    *existing = record.clone();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let endpoint = |targets: &[&str], ttl: i64| Endpoint {
+        record_ttl: Some(ttl),
+        ..Endpoint::test("www.example.org", Type::A, targets)
+    };
+
+    let provider = RecordStoreProvider::new(Store::default())
+        .with_domain_filter(DomainFilter::new(["example.org"]));
+
+    provider
+        .set_records(vec![Change::Create(endpoint(
+            &["192.168.0.1", "192.168.0.2", "192.168.0.3"],
+            300,
+        ))])
+        .await
+        .unwrap();
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![endpoint(
+            &["192.168.0.1", "192.168.0.2", "192.168.0.3"],
+            300
+        )]
+    );
+
+    provider.store().operations.lock().unwrap().clear();
+    provider
+        .set_records(vec![Change::Update {
+            old: endpoint(&["192.168.0.1", "192.168.0.2", "192.168.0.3"], 300),
+            new: endpoint(&["192.168.0.1", "192.168.0.4"], 300),
+        }])
+        .await
+        .unwrap();
+
+    // Only the changed targets are touched, reusing a removed record for the added target.
+    assert_eq!(
+        *provider.store().operations.lock().unwrap(),
+        vec![
+            Operation::Update(3, "192.168.0.4".to_string(), Some(300)),
+            Operation::Delete(2),
+        ]
+    );
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![endpoint(&["192.168.0.1", "192.168.0.4"], 300)]
+    );
+
+    provider.store().operations.lock().unwrap().clear();
+    provider
+        .set_records(vec![Change::Delete(endpoint(
+            &["192.168.0.1", "192.168.0.4"],
+            300,
+        ))])
+        .await
+        .unwrap();
+    assert_eq!(
+        *provider.store().operations.lock().unwrap(),
+        vec![Operation::Delete(1), Operation::Delete(3)]
+    );
+    assert_eq!(provider.get_records().await.unwrap(), vec![]);
+
+    // Records created earlier in the same batch are known to later changes.
+    provider.store().operations.lock().unwrap().clear();
+    provider
+        .set_records(vec![
+            Change::Create(endpoint(&["192.168.0.1"], 300)),
+            Change::Update {
+                old: endpoint(&["192.168.0.1"], 300),
+                new: endpoint(&["192.168.0.1", "192.168.0.2"], 600),
+            },
+            Change::Delete(endpoint(&["192.168.0.1", "192.168.0.2"], 600)),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        *provider.store().operations.lock().unwrap(),
+        vec![
+            Operation::Create("192.168.0.1".to_string()),
+            Operation::Update(4, "192.168.0.1".to_string(), Some(600)),
+            Operation::Create("192.168.0.2".to_string()),
+            Operation::Delete(4),
+            Operation::Delete(5),
+        ]
+    );
+    assert_eq!(provider.get_records().await.unwrap(), vec![]);
+
+    let filtered = Endpoint::test("www.example.com", Type::A, &["192.168.0.1"]);
+    assert_eq!(
+        provider
+            .set_records(vec![
+                Change::Create(endpoint(&["192.168.0.1"], 300)),
+                Change::Create(filtered),
+            ])
+            .await,
+        Err(PartialApplyError {
+            failed: None,
+            error: FilteredDomain(DomainName::try_from("www.example.com").unwrap())
+        })
+    );
+    assert_eq!(provider.get_records().await.unwrap(), vec![]);
+
+    // A failure part of the way through reports the failed change, so the
+    // preceding ones can be rolled back.
+    let changes = vec![
+        Change::Create(endpoint(&["192.168.0.1"], 300)),
+        Change::Update {
+            old: endpoint(&["192.168.0.1"], 300),
+            new: endpoint(&["192.168.0.2"], 300),
+        },
+        Change::Create(Endpoint::test(
+            "fail.example.org",
+            Type::A,
+            &["192.168.0.1"],
+        )),
+    ];
+    let err = provider.set_records(changes.clone()).await.unwrap_err();
+    assert_eq!(err.failed, Some(2));
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![endpoint(&["192.168.0.2"], 300)]
+    );
+
+    provider
+        .set_records(changes.rollback_partial(2))
+        .await
+        .unwrap();
+    assert_eq!(provider.get_records().await.unwrap(), vec![]);
+}
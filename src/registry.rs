@@ -0,0 +1,544 @@
+use std::collections::{BTreeMap, HashMap};
+
+use kubizone_common::{DomainName, FullyQualifiedDomainName, Type};
+use regex::Regex;
+
+#[cfg(feature = "encryption")]
+use crate::{encryption, EncryptionKey};
+use crate::{parse_type, Endpoint, EndpointIdent, OWNER_LABEL};
+
+/// Heritage written to the start of every TXT registry record.
+pub const HERITAGE: &str = "external-dns";
+
+/// Label holding the Kubernetes resource which produced an endpoint,
+/// such as `ingress/default/my-ingress`.
+pub const RESOURCE_LABEL: &str = "resource";
+
+/// Placeholder in a TXT registry prefix or suffix, which is replaced by
+/// the lowercase record type of the endpoint.
+pub const RECORD_TYPE_TEMPLATE: &str = "%{record_type}";
+
+/// Label holding the base64 encoded nonce of an encrypted TXT registry record.
+///
+/// Set on endpoints read from an encrypted registry, so their companion
+/// records can be regenerated without changing the ciphertext. The label
+/// itself is never written to the registry.
+pub const ENCRYPTION_NONCE_LABEL: &str = "txt-encryption-nonce";
+
+/// Prefix of the label keys within a TXT registry record.
+const LABEL_PREFIX: &str = "external-dns/";
+
+/// Produced when a TXT record cannot be read as a TXT registry record.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RegistryError {
+    /// The record does not start with `heritage=external-dns`.
+    #[error("record does not have heritage={HERITAGE}")]
+    InvalidHeritage,
+
+    /// A label is not of the form `external-dns/key=value`.
+    #[error("invalid label {0:?}")]
+    InvalidLabel(String),
+
+    /// The name of the companion TXT record is not a valid domain name.
+    #[error("invalid TXT record name {0:?}")]
+    InvalidName(String),
+
+    /// The [`ENCRYPTION_NONCE_LABEL`] of an endpoint is not a base64 encoded 12 byte nonce.
+    #[cfg(feature = "encryption")]
+    #[error("invalid encryption nonce {0:?}")]
+    InvalidNonce(String),
+}
+
+/// Serialize labels into the payload of a TXT registry record,
+/// wrapped in double quotes as External-DNS does.
+///
+/// Labels are sorted by key, producing payloads like
+/// `"heritage=external-dns,external-dns/owner=default,external-dns/resource=ingress/default/web"`.
+pub fn serialize_labels(labels: &HashMap<String, String>) -> String {
+    format!("\"{}\"", serialize_plain(labels))
+}
+
+/// Serialize labels without surrounding quotes, omitting the [`ENCRYPTION_NONCE_LABEL`].
+fn serialize_plain(labels: &HashMap<String, String>) -> String {
+    let labels: BTreeMap<&String, &String> = labels
+        .iter()
+        .filter(|(key, _)| *key != ENCRYPTION_NONCE_LABEL)
+        .collect();
+
+    let mut payload = format!("heritage={HERITAGE}");
+    for (key, value) in labels {
+        payload.push_str(&format!(",{LABEL_PREFIX}{key}={value}"));
+    }
+
+    payload
+}
+
+fn unquote(payload: &str) -> &str {
+    payload
+        .strip_prefix('"')
+        .and_then(|payload| payload.strip_suffix('"'))
+        .unwrap_or(payload)
+}
+
+/// Parse the payload of a TXT registry record into labels.
+///
+/// Surrounding double quotes are optional.
+pub fn parse_labels(payload: &str) -> Result<HashMap<String, String>, RegistryError> {
+    let mut tokens = unquote(payload).split(',');
+
+    if tokens.next() != Some(&format!("heritage={HERITAGE}")) {
+        return Err(RegistryError::InvalidHeritage);
+    }
+
+    tokens
+        .map(|token| {
+            token
+                .strip_prefix(LABEL_PREFIX)
+                .and_then(|label| label.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| RegistryError::InvalidLabel(token.to_string()))
+        })
+        .collect()
+}
+
+/// Reads and writes External-DNS' TXT registry, which records the owner
+/// and origin of every managed endpoint in a companion TXT record.
+///
+/// Companion records are named after the endpoint, with the record type
+/// and configured prefix or suffix applied to the first label. With the
+/// default configuration, the A record `www.example.org` is accompanied by
+/// the TXT record `a-www.example.org`. If the prefix or suffix contains
+/// [`RECORD_TYPE_TEMPLATE`], the record type is placed there instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxtRegistry {
+    /// Owner written to the companion records, equivalent to `--txt-owner-id`.
+    pub owner: String,
+
+    /// Prefix of companion record names, equivalent to `--txt-prefix`.
+    pub prefix: String,
+
+    /// Suffix of companion record names, equivalent to `--txt-suffix`.
+    pub suffix: String,
+
+    /// Replacement for a leading `*` in companion record names,
+    /// equivalent to `--txt-wildcard-replacement`.
+    pub wildcard_replacement: Option<String>,
+
+    /// Key used to encrypt companion records, equivalent to `--txt-encrypt-enabled`
+    /// and `--txt-encrypt-aes-key`.
+    #[cfg(feature = "encryption")]
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+impl TxtRegistry {
+    /// Construct a registry writing records owned by `owner`.
+    pub fn new(owner: impl Into<String>) -> Self {
+        TxtRegistry {
+            owner: owner.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Prefix companion record names with the given string.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Suffix the first label of companion record names with the given string.
+    pub fn with_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.suffix = suffix.into();
+        self
+    }
+
+    /// Replace a leading `*` in companion record names with the given string.
+    pub fn with_wildcard_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.wildcard_replacement = Some(replacement.into());
+        self
+    }
+
+    /// Encrypt companion records with the given key.
+    ///
+    /// Records which cannot be decrypted with the key are still read as
+    /// plain text, so registries can be migrated to encryption gradually.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption_key(mut self, key: EncryptionKey) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
+
+    /// Returns true if the prefix or suffix determines where the record type is placed.
+    fn templated(&self) -> bool {
+        self.prefix.contains(RECORD_TYPE_TEMPLATE) || self.suffix.contains(RECORD_TYPE_TEMPLATE)
+    }
+
+    /// Name of the companion TXT record of an endpoint.
+    pub fn txt_name(&self, identity: &EndpointIdent) -> String {
+        let record_type = identity.record_type.to_string().to_ascii_lowercase();
+        let prefix = self.prefix.replace(RECORD_TYPE_TEMPLATE, &record_type);
+        let suffix = self.suffix.replace(RECORD_TYPE_TEMPLATE, &record_type);
+
+        let dns_name = identity.dns_name.to_string();
+        let dns_name = dns_name.trim_end_matches('.');
+        let (mut first, rest) = match dns_name.split_once('.') {
+            Some((first, rest)) => (first.to_string(), Some(rest)),
+            None => (dns_name.to_string(), None),
+        };
+
+        if let Some(replacement) = self.wildcard_replacement.as_ref().filter(|_| first == "*") {
+            first = replacement.clone();
+        }
+
+        if !self.templated() {
+            first = format!("{record_type}-{first}");
+        }
+
+        match rest {
+            Some(rest) => format!("{prefix}{first}{suffix}.{rest}"),
+            None => format!("{prefix}{first}{suffix}"),
+        }
+    }
+
+    /// Construct the companion TXT record of an endpoint.
+    ///
+    /// The record carries the labels of the endpoint, with the owner label
+    /// replaced by the owner of this registry. If an encryption key is set,
+    /// the payload is encrypted using the nonce in the endpoint's
+    /// [`ENCRYPTION_NONCE_LABEL`], or a random one if it has none.
+    pub fn companion(&self, endpoint: &Endpoint) -> Result<Endpoint, RegistryError> {
+        let name = self.txt_name(&endpoint.identity);
+        let dns_name =
+            DomainName::try_from(name.as_str()).map_err(|_| RegistryError::InvalidName(name))?;
+
+        let mut labels = endpoint.labels.clone();
+        labels.insert(OWNER_LABEL.to_string(), self.owner.clone());
+
+        Ok(Endpoint {
+            identity: EndpointIdent {
+                dns_name,
+                record_type: Type::TXT,
+            },
+            set_identifier: endpoint.set_identifier.clone(),
+            targets: vec![self.serialize(&labels)?],
+            record_ttl: None,
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        })
+    }
+
+    /// Construct the companion TXT records of all the endpoints.
+    pub fn companions(&self, endpoints: &[Endpoint]) -> Result<Vec<Endpoint>, RegistryError> {
+        endpoints
+            .iter()
+            .map(|endpoint| self.companion(endpoint))
+            .collect()
+    }
+
+    /// Separate registry records from the endpoints returned by a provider,
+    /// and apply the labels they contain to the endpoints they accompany.
+    ///
+    /// TXT records which do not parse as registry records are left as-is.
+    /// Registry records written in the older format, which does not include
+    /// the record type in the name, apply to endpoints of every type, unless
+    /// a record in the current format exists.
+    pub fn parse(&self, records: Vec<Endpoint>) -> Vec<Endpoint> {
+        let mapper = Mapper::new(self);
+
+        type Key = (FullyQualifiedDomainName, Option<Type>, Option<String>);
+        let mut registry: HashMap<Key, HashMap<String, String>> = HashMap::new();
+        let mut endpoints = Vec::new();
+
+        for record in records {
+            let labels = (record.identity.record_type == Type::TXT)
+                .then(|| record.targets.iter().find_map(|t| self.deserialize(t).ok()))
+                .flatten();
+
+            let name = labels
+                .as_ref()
+                .and_then(|_| mapper.endpoint_name(&record.identity.dns_name.to_string()))
+                .and_then(|(name, record_type)| {
+                    DomainName::try_from(name.as_str())
+                        .ok()
+                        .map(|name| (name.to_fully_qualified(), record_type))
+                });
+
+            match (labels, name) {
+                (Some(labels), Some((name, record_type))) => {
+                    registry.insert((name, record_type, record.set_identifier), labels);
+                }
+                _ => endpoints.push(record),
+            }
+        }
+
+        for endpoint in &mut endpoints {
+            let name = endpoint.identity.dns_name.to_fully_qualified();
+            let set_identifier = endpoint.set_identifier.clone();
+
+            let labels = registry
+                .get(&(
+                    name.clone(),
+                    Some(endpoint.identity.record_type),
+                    set_identifier.clone(),
+                ))
+                .or_else(|| registry.get(&(name, None, set_identifier)));
+
+            if let Some(labels) = labels {
+                endpoint.labels.extend(labels.clone());
+            }
+        }
+
+        endpoints
+    }
+
+    /// Serialize labels, encrypting them if an encryption key is set.
+    fn serialize(&self, labels: &HashMap<String, String>) -> Result<String, RegistryError> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encryption_key {
+            let nonce = match labels.get(ENCRYPTION_NONCE_LABEL) {
+                Some(nonce) => encryption::decode_nonce(nonce)
+                    .ok_or_else(|| RegistryError::InvalidNonce(nonce.clone()))?,
+                None => encryption::generate_nonce(),
+            };
+
+            return Ok(format!(
+                "\"{}\"",
+                key.encrypt(&serialize_plain(labels), &nonce)
+            ));
+        }
+
+        Ok(serialize_labels(labels))
+    }
+
+    /// Parse labels, decrypting them first if an encryption key is set
+    /// and the payload is encrypted.
+    fn deserialize(&self, payload: &str) -> Result<HashMap<String, String>, RegistryError> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encryption_key {
+            if let Ok((text, nonce)) = key.decrypt(unquote(payload)) {
+                let mut labels = parse_labels(&text)?;
+                labels.insert(
+                    ENCRYPTION_NONCE_LABEL.to_string(),
+                    encryption::encode_nonce(&nonce),
+                );
+                return Ok(labels);
+            }
+        }
+
+        parse_labels(payload)
+    }
+}
+
+/// Maps the names of companion TXT records back to the names and record
+/// types of the endpoints they accompany.
+struct Mapper {
+    /// Matches names in the current format, capturing the first label and the record type.
+    current: Regex,
+    /// Matches names in the older format, which omits the record type.
+    legacy: Regex,
+    wildcard_replacement: Option<String>,
+}
+
+impl Mapper {
+    fn new(registry: &TxtRegistry) -> Self {
+        let affix = |affix: &str, record_type: &str| -> String {
+            affix
+                .split(RECORD_TYPE_TEMPLATE)
+                .map(|part| regex::escape(&part.to_ascii_lowercase()))
+                .collect::<Vec<_>>()
+                .join(record_type)
+        };
+
+        // The record type is captured by the first unnamed group.
+        let record_type = if registry.templated() {
+            ""
+        } else {
+            "([a-z0-9]+)-"
+        };
+
+        let prefix = affix(&registry.prefix, "([a-z0-9]+)");
+        let suffix = affix(&registry.suffix, "([a-z0-9]+)");
+        let current =
+            format!(r"^{prefix}{record_type}(?P<first>[^.]+){suffix}(?:\.(?P<rest>.+))?$");
+
+        let prefix = affix(&registry.prefix, "");
+        let suffix = affix(&registry.suffix, "");
+        let legacy = format!(r"^{prefix}(?P<first>[^.]+){suffix}(?:\.(?P<rest>.+))?$");
+
+        Mapper {
+            current: Regex::new(&current).expect("escaped affixes form a valid regex"),
+            legacy: Regex::new(&legacy).expect("escaped affixes form a valid regex"),
+            wildcard_replacement: registry.wildcard_replacement.clone(),
+        }
+    }
+
+    /// Name and record type of the endpoint accompanied by the TXT record,
+    /// or `None` if the name does not match the prefix and suffix.
+    fn endpoint_name(&self, txt_name: &str) -> Option<(String, Option<Type>)> {
+        let txt_name = txt_name.trim_end_matches('.').to_ascii_lowercase();
+
+        // Names whose record type does not parse are in the older format.
+        let current = self.current.captures(&txt_name).and_then(|captures| {
+            let record_type = self
+                .current
+                .capture_names()
+                .zip(captures.iter())
+                .skip(1)
+                .find_map(|(name, capture)| capture.filter(|_| name.is_none()))
+                .and_then(|capture| parse_type(capture.as_str()))?;
+
+            Some((captures, Some(record_type)))
+        });
+
+        let (captures, record_type) = match current {
+            Some(current) => current,
+            None => (self.legacy.captures(&txt_name)?, None),
+        };
+
+        let mut first = &captures["first"];
+        if self.wildcard_replacement.as_deref() == Some(first) {
+            first = "*";
+        }
+
+        let name = match captures.name("rest") {
+            Some(rest) => format!("{first}.{}", rest.as_str()),
+            None => first.to_string(),
+        };
+
+        Some((name, record_type))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn txt_registry() {
+    let endpoint =
+        |name: &str, record_type: Type, target: &str| Endpoint::test(name, record_type, &[target]);
+
+    let mut www = endpoint("www.example.org", Type::A, "192.168.0.1");
+    www.labels.insert(
+        RESOURCE_LABEL.to_string(),
+        "ingress/default/web".to_string(),
+    );
+
+    let registry = TxtRegistry::new("default");
+    let companion = registry.companion(&www).unwrap();
+    assert_eq!(
+        companion,
+        endpoint(
+            "a-www.example.org",
+            Type::TXT,
+            "\"heritage=external-dns,external-dns/owner=default,external-dns/resource=ingress/default/web\""
+        )
+    );
+
+    let identity = |name: &str, record_type: Type| EndpointIdent {
+        dns_name: DomainName::try_from(name).unwrap(),
+        record_type,
+    };
+    assert_eq!(
+        TxtRegistry::new("default")
+            .with_prefix("txt.")
+            .txt_name(&identity("www.example.org", Type::CNAME)),
+        "txt.cname-www.example.org"
+    );
+    assert_eq!(
+        TxtRegistry::new("default")
+            .with_suffix("-%{record_type}")
+            .with_wildcard_replacement("any")
+            .txt_name(&identity("*.example.org", Type::AAAA)),
+        "any-aaaa.example.org"
+    );
+
+    // Registry records are removed, and their labels applied.
+    let records = vec![
+        www.clone(),
+        companion,
+        endpoint("mail.example.org", Type::MX, "10 mx.example.org"),
+        endpoint("mail.example.org", Type::TXT, "v=spf1 -all"),
+        endpoint(
+            "mail.example.org",
+            Type::TXT,
+            "heritage=external-dns,external-dns/owner=other",
+        ),
+    ];
+
+    let mut labelled = www.clone();
+    labelled
+        .labels
+        .insert(OWNER_LABEL.to_string(), "default".to_string());
+    let mut mx = endpoint("mail.example.org", Type::MX, "10 mx.example.org");
+    mx.labels
+        .insert(OWNER_LABEL.to_string(), "other".to_string());
+    let mut spf = endpoint("mail.example.org", Type::TXT, "v=spf1 -all");
+    spf.labels
+        .insert(OWNER_LABEL.to_string(), "other".to_string());
+
+    assert_eq!(registry.parse(records), vec![labelled, mx, spf]);
+
+    // Templated suffixes and wildcard replacements are reversed.
+    let registry = TxtRegistry::new("default")
+        .with_suffix("-%{record_type}")
+        .with_wildcard_replacement("any");
+    let wildcard = endpoint("*.example.org", Type::AAAA, "::1");
+    let companion = registry.companion(&wildcard).unwrap();
+
+    let mut labelled = wildcard.clone();
+    labelled
+        .labels
+        .insert(OWNER_LABEL.to_string(), "default".to_string());
+    assert_eq!(registry.parse(vec![wildcard, companion]), vec![labelled]);
+
+    assert_eq!(
+        parse_labels("heritage=foreign,external-dns/owner=default"),
+        Err(RegistryError::InvalidHeritage)
+    );
+}
+
+#[cfg(all(test, feature = "encryption"))]
+#[test]
+fn encrypted_txt_registry() {
+    let endpoint =
+        |name: &str, record_type: Type, target: &str| Endpoint::test(name, record_type, &[target]);
+
+    let key = EncryptionKey::try_from("passphrasewhichneedstobe32bytes!".as_bytes()).unwrap();
+    let registry = TxtRegistry::new("default").with_encryption_key(key);
+
+    // Gzip compressed, AES-256-GCM encrypted and base64 encoded in the same
+    // way as External-DNS' endpoint.EncryptText.
+    let fixture = include_str!("../tests/fixtures/registry/encrypted.txt").trim();
+
+    let www = endpoint("www.example.org", Type::A, "192.168.0.1");
+    let mut labelled = www.clone();
+    labelled.labels.extend([
+        (OWNER_LABEL.to_string(), "default".to_string()),
+        (
+            RESOURCE_LABEL.to_string(),
+            "ingress/default/web".to_string(),
+        ),
+        (
+            ENCRYPTION_NONCE_LABEL.to_string(),
+            "nB9ae+PQSCpsEfDk".to_string(),
+        ),
+    ]);
+
+    let parsed = registry.parse(vec![www, endpoint("a-www.example.org", Type::TXT, fixture)]);
+    assert_eq!(parsed, vec![labelled.clone()]);
+
+    // Regenerated companions reuse the nonce, and decrypt to the same labels.
+    let companion = registry.companion(&labelled).unwrap();
+    assert!(companion.targets[0].starts_with("\"nB9ae+PQSCpsEfDk"));
+    assert_eq!(
+        registry.parse(vec![labelled.clone(), companion]),
+        vec![labelled]
+    );
+
+    // Plain text records are still readable.
+    let plain = TxtRegistry::new("default")
+        .companion(&endpoint("api.example.org", Type::A, "192.168.0.2"))
+        .unwrap();
+    let parsed = registry.parse(vec![
+        endpoint("api.example.org", Type::A, "192.168.0.2"),
+        plain,
+    ]);
+    assert_eq!(parsed[0].labels[OWNER_LABEL], "default");
+}
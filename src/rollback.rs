@@ -0,0 +1,105 @@
+use crate::Change;
+
+impl Change {
+    /// Change which undoes the effects of this one.
+    ///
+    /// Creates become deletes, deletes become creates, and the `old` and
+    /// `new` endpoints of updates are swapped.
+    pub fn invert(self) -> Change {
+        match self {
+            Change::Update { old, new } => Change::Update { old: new, new: old },
+            Change::Delete(endpoint) => Change::Create(endpoint),
+            Change::Create(endpoint) => Change::Delete(endpoint),
+        }
+    }
+}
+
+/// Utility trait for computing the changes which undo a list of changes.
+pub trait Rollback {
+    /// Compute the changes which undo all of the given changes.
+    ///
+    /// The changes are inverted and reversed, so applying the rollback in
+    /// order undoes the last change first.
+    fn rollback(self) -> Vec<Change>;
+
+    /// Compute the changes which undo only the first `applied` changes.
+    ///
+    /// Useful for undoing a partially applied batch, where a provider
+    /// applied changes in order, and failed part of the way through.
+    /// See [`PartialApplyError`].
+    fn rollback_partial(self, applied: usize) -> Vec<Change>;
+}
+
+/// Error of a provider which applies changes one at a time, and may
+/// therefore fail part of the way through a batch.
+///
+/// If the error occurred while applying a change, `failed` is its index.
+/// All of the changes before it have been applied, while the failed change
+/// itself may have been partially applied. Passing the index to
+/// [`Rollback::rollback_partial`] undoes the changes which were fully applied.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{error}")]
+pub struct PartialApplyError<E> {
+    /// Index of the change which failed, if any.
+    pub failed: Option<usize>,
+
+    /// The underlying error.
+    pub error: E,
+}
+
+impl<E> From<E> for PartialApplyError<E> {
+    fn from(error: E) -> Self {
+        PartialApplyError {
+            failed: None,
+            error,
+        }
+    }
+}
+
+impl Rollback for Vec<Change> {
+    fn rollback(self) -> Vec<Change> {
+        self.into_iter().rev().map(Change::invert).collect()
+    }
+
+    fn rollback_partial(mut self, applied: usize) -> Vec<Change> {
+        self.truncate(applied);
+        self.rollback()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn rollback_changes() {
+    use crate::{Endpoint, EndpointDiff};
+    use kubizone_common::Type;
+
+    let endpoint = |name: &str, target: &str| Endpoint::test(name, Type::A, &[target]);
+
+    let before = vec![
+        endpoint("update.org.", "192.168.0.1"),
+        endpoint("delete.org.", "192.168.0.1"),
+    ];
+    let after = vec![
+        endpoint("update.org.", "192.168.0.2"),
+        endpoint("create.org.", "192.168.0.1"),
+    ];
+
+    let changes = before.clone().difference(after.clone());
+
+    assert_eq!(
+        changes.clone().rollback(),
+        vec![
+            Change::Delete(endpoint("create.org.", "192.168.0.1")),
+            Change::Update {
+                old: endpoint("update.org.", "192.168.0.2"),
+                new: endpoint("update.org.", "192.168.0.1"),
+            },
+            Change::Create(endpoint("delete.org.", "192.168.0.1")),
+        ]
+    );
+
+    assert_eq!(
+        changes.rollback_partial(1),
+        vec![Change::Create(endpoint("delete.org.", "192.168.0.1"))]
+    );
+}
@@ -0,0 +1,260 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::{Change, DiffOptions, Endpoint, EndpointIdent, EndpointName};
+
+/// Set of endpoints, indexed by identity and set identifier.
+///
+/// Intended as the backing store of providers, which can use [`EndpointSet::apply`]
+/// to apply the changes passed to [`Provider::set_records`](crate::Provider::set_records).
+///
+/// Endpoints are returned in the order in which they were created.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointSet {
+    endpoints: HashMap<(EndpointIdent, Option<String>), (u64, Endpoint)>,
+    sequence: u64,
+}
+
+/// Produced when a list of changes cannot be applied to an [`EndpointSet`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ApplyError {
+    /// The endpoint to update or delete does not exist.
+    #[error("endpoint {} does not exist", EndpointName::from(.0.as_ref()))]
+    Missing(Box<Endpoint>),
+
+    /// The endpoint to update or delete does not match the existing one.
+    #[error("endpoint {} does not match the existing endpoint", EndpointName::from(.0.as_ref()))]
+    Mismatch(Box<Endpoint>),
+
+    /// The endpoint to create already exists.
+    #[error("endpoint {} already exists", EndpointName::from(.0.as_ref()))]
+    AlreadyExists(Box<Endpoint>),
+}
+
+type Key = (EndpointIdent, Option<String>);
+
+fn key(endpoint: &Endpoint) -> Key {
+    (endpoint.identity.clone(), endpoint.set_identifier.clone())
+}
+
+impl EndpointSet {
+    /// Construct an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of endpoints in the set.
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Returns true if the set contains no endpoints.
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// Look up an endpoint by its identity and set identifier.
+    pub fn get(&self, identity: &EndpointIdent, set_identifier: Option<&str>) -> Option<&Endpoint> {
+        self.endpoints
+            .get(&(identity.clone(), set_identifier.map(str::to_string)))
+            .map(|(_, endpoint)| endpoint)
+    }
+
+    /// Insert an endpoint, replacing any existing endpoint with the same
+    /// identity and set identifier.
+    pub fn insert(&mut self, endpoint: Endpoint) -> Option<Endpoint> {
+        match self.endpoints.entry(key(&endpoint)) {
+            Entry::Occupied(mut entry) => Some(std::mem::replace(&mut entry.get_mut().1, endpoint)),
+            Entry::Vacant(entry) => {
+                self.sequence += 1;
+                entry.insert((self.sequence, endpoint));
+                None
+            }
+        }
+    }
+
+    /// Remove the endpoint with the given identity and set identifier.
+    pub fn remove(
+        &mut self,
+        identity: &EndpointIdent,
+        set_identifier: Option<&str>,
+    ) -> Option<Endpoint> {
+        self.endpoints
+            .remove(&(identity.clone(), set_identifier.map(str::to_string)))
+            .map(|(_, endpoint)| endpoint)
+    }
+
+    /// Endpoints in the set, in the order they were created.
+    pub fn to_vec(&self) -> Vec<Endpoint> {
+        let mut endpoints: Vec<_> = self.endpoints.values().collect();
+        endpoints.sort_by_key(|(sequence, _)| *sequence);
+        endpoints
+            .into_iter()
+            .map(|(_, endpoint)| endpoint.clone())
+            .collect()
+    }
+
+    /// Apply the changes to the set.
+    ///
+    /// The endpoints being updated or deleted must exist, and be equivalent
+    /// to the `old` or deleted endpoint of the change, and the endpoints being
+    /// created must not already exist.
+    ///
+    /// Changes are applied atomically: if any change fails, the set is left
+    /// untouched.
+    pub fn apply(&mut self, changes: Vec<Change>) -> Result<(), ApplyError> {
+        let mut undo = Vec::new();
+
+        for change in changes {
+            if let Err(err) = self.apply_change(change, &mut undo) {
+                for (key, previous) in undo.into_iter().rev() {
+                    match previous {
+                        Some(previous) => self.endpoints.insert(key, previous),
+                        None => self.endpoints.remove(&key),
+                    };
+                }
+
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_change(
+        &mut self,
+        change: Change,
+        undo: &mut Vec<(Key, Option<(u64, Endpoint)>)>,
+    ) -> Result<(), ApplyError> {
+        match change {
+            Change::Create(endpoint) => {
+                let key = key(&endpoint);
+                if self.endpoints.contains_key(&key) {
+                    return Err(ApplyError::AlreadyExists(Box::new(endpoint)));
+                }
+
+                self.sequence += 1;
+                self.endpoints
+                    .insert(key.clone(), (self.sequence, endpoint));
+                undo.push((key, None));
+            }
+            Change::Delete(endpoint) => {
+                let key = self.verify(&endpoint)?;
+                let previous = self.endpoints.remove(&key);
+                undo.push((key, previous));
+            }
+            Change::Update { old, new } => {
+                let old_key = self.verify(&old)?;
+                let new_key = key(&new);
+
+                if old_key != new_key && self.endpoints.contains_key(&new_key) {
+                    return Err(ApplyError::AlreadyExists(Box::new(new)));
+                }
+
+                let previous = self.endpoints.remove(&old_key);
+                let sequence = previous.as_ref().map_or(0, |(sequence, _)| *sequence);
+                undo.push((old_key, previous));
+
+                let replaced = self.endpoints.insert(new_key.clone(), (sequence, new));
+                undo.push((new_key, replaced));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify that an equivalent endpoint exists in the set, and return its key.
+    fn verify(&self, endpoint: &Endpoint) -> Result<Key, ApplyError> {
+        let key = key(endpoint);
+
+        match self.endpoints.get(&key) {
+            None => Err(ApplyError::Missing(Box::new(endpoint.clone()))),
+            Some((_, existing)) if !DiffOptions::default().equivalent(existing, endpoint) => {
+                Err(ApplyError::Mismatch(Box::new(endpoint.clone())))
+            }
+            Some(_) => Ok(key),
+        }
+    }
+}
+
+impl FromIterator<Endpoint> for EndpointSet {
+    fn from_iter<T: IntoIterator<Item = Endpoint>>(iter: T) -> Self {
+        let mut set = EndpointSet::new();
+
+        for endpoint in iter {
+            set.insert(endpoint);
+        }
+
+        set
+    }
+}
+
+impl From<Vec<Endpoint>> for EndpointSet {
+    fn from(endpoints: Vec<Endpoint>) -> Self {
+        EndpointSet::from_iter(endpoints)
+    }
+}
+
+impl From<EndpointSet> for Vec<Endpoint> {
+    fn from(set: EndpointSet) -> Self {
+        set.to_vec()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn apply_changes() {
+    use kubizone_common::Type;
+
+    let endpoint = |name: &str, target: &str| Endpoint::test(name, Type::A, &[target]);
+
+    let mut set = EndpointSet::from(vec![
+        endpoint("update.org.", "192.168.0.1"),
+        endpoint("delete.org.", "192.168.0.1"),
+    ]);
+
+    set.apply(vec![
+        Change::Delete(endpoint("delete.org.", "192.168.0.1")),
+        Change::Update {
+            old: endpoint("update.org.", "192.168.0.1"),
+            new: endpoint("update.org.", "192.168.0.2"),
+        },
+        Change::Create(endpoint("create.org.", "192.168.0.1")),
+    ])
+    .unwrap();
+
+    let expected = vec![
+        endpoint("update.org.", "192.168.0.2"),
+        endpoint("create.org.", "192.168.0.1"),
+    ];
+    assert_eq!(set.to_vec(), expected);
+
+    // Failing changes must leave the set untouched.
+    assert_eq!(
+        set.apply(vec![
+            Change::Delete(endpoint("update.org.", "192.168.0.2")),
+            Change::Create(endpoint("create.org.", "192.168.0.1")),
+        ]),
+        Err(ApplyError::AlreadyExists(Box::new(endpoint(
+            "create.org.",
+            "192.168.0.1"
+        ))))
+    );
+    assert_eq!(
+        set.apply(vec![Change::Delete(endpoint("update.org.", "192.168.0.1"))]),
+        Err(ApplyError::Mismatch(Box::new(endpoint(
+            "update.org.",
+            "192.168.0.1"
+        ))))
+    );
+    assert_eq!(
+        set.apply(vec![Change::Delete(endpoint(
+            "missing.org.",
+            "192.168.0.1"
+        ))]),
+        Err(ApplyError::Missing(Box::new(endpoint(
+            "missing.org.",
+            "192.168.0.1"
+        ))))
+    );
+    assert_eq!(set.to_vec(), expected);
+}
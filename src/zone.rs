@@ -0,0 +1,185 @@
+use kubizone_common::{DomainName, FullyQualifiedDomainName};
+
+use crate::{Change, DomainFilter, Endpoint};
+
+/// Set of DNS zones, used to find the zone each endpoint belongs to.
+///
+/// Endpoints belong to the most specific zone containing them, so with the
+/// zones `example.org` and `sub.example.org`, the endpoint `www.sub.example.org`
+/// belongs to the latter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Zones {
+    zones: Vec<(DomainName, FullyQualifiedDomainName)>,
+}
+
+/// Items partitioned by the zone they belong to, as produced by
+/// [`Zones::partition_endpoints`] and [`Zones::partition_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition<T> {
+    /// Every zone along with the items belonging to it, in the order the
+    /// zones were given. Zones without any items are included.
+    pub zones: Vec<(DomainName, Vec<T>)>,
+
+    /// Items which do not belong to any zone, in their original order.
+    pub unmatched: Vec<T>,
+}
+
+impl Zones {
+    /// Construct a set of zones, ignoring duplicates.
+    pub fn new<I: IntoIterator<Item = DomainName>>(zones: I) -> Self {
+        let mut out = Zones::default();
+
+        for zone in zones {
+            let name = zone.to_fully_qualified();
+
+            if !out.zones.iter().any(|(_, existing)| *existing == name) {
+                out.zones.push((zone, name));
+            }
+        }
+
+        out
+    }
+
+    /// Construct a set of zones from the domains included by a filter,
+    /// such as the one returned by [`Provider::init`](crate::Provider::init).
+    ///
+    /// Leading dots are removed, and filters which are not valid domain
+    /// names are ignored, as are exclusions and regular expressions.
+    pub fn from_filter(filter: &DomainFilter) -> Self {
+        Zones::new(
+            filter
+                .filters
+                .iter()
+                .filter_map(|zone| DomainName::try_from(zone.trim_start_matches('.')).ok()),
+        )
+    }
+
+    /// Zones in the set, in the order they were given.
+    pub fn iter(&self) -> impl Iterator<Item = &DomainName> + '_ {
+        self.zones.iter().map(|(zone, _)| zone)
+    }
+
+    /// Index of the most specific zone containing the name.
+    fn position(&self, dns_name: &DomainName) -> Option<usize> {
+        let name = dns_name.to_fully_qualified();
+
+        self.zones
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, zone))| name == *zone || name.is_subdomain_of(zone))
+            .max_by_key(|(_, (_, zone))| zone.iter().len())
+            .map(|(index, _)| index)
+    }
+
+    /// Most specific zone containing the name, if any.
+    pub fn find(&self, dns_name: &DomainName) -> Option<&DomainName> {
+        self.position(dns_name).map(|index| &self.zones[index].0)
+    }
+
+    fn partition<T>(&self, items: Vec<T>, name: impl Fn(&T) -> &DomainName) -> Partition<T> {
+        let mut partition = Partition {
+            zones: self
+                .zones
+                .iter()
+                .map(|(zone, _)| (zone.clone(), Vec::new()))
+                .collect(),
+            unmatched: Vec::new(),
+        };
+
+        for item in items {
+            match self.position(name(&item)) {
+                Some(index) => partition.zones[index].1.push(item),
+                None => partition.unmatched.push(item),
+            }
+        }
+
+        partition
+    }
+
+    /// Partition endpoints by the zone they belong to.
+    pub fn partition_endpoints(&self, endpoints: Vec<Endpoint>) -> Partition<Endpoint> {
+        self.partition(endpoints, |endpoint| &endpoint.identity.dns_name)
+    }
+
+    /// Partition changes by the zone of the endpoint they affect.
+    ///
+    /// Updates are placed according to their `old` endpoint, which shares
+    /// its name with the `new` one.
+    pub fn partition_changes(&self, changes: Vec<Change>) -> Partition<Change> {
+        self.partition(changes, |change| match change {
+            Change::Update { old, .. } => &old.identity.dns_name,
+            Change::Delete(endpoint) | Change::Create(endpoint) => &endpoint.identity.dns_name,
+        })
+    }
+}
+
+impl FromIterator<DomainName> for Zones {
+    fn from_iter<T: IntoIterator<Item = DomainName>>(iter: T) -> Self {
+        Zones::new(iter)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn zone_partitioning() {
+    use kubizone_common::Type;
+
+    let endpoint = |name: &str| Endpoint::test(name, Type::A, &["192.168.0.1"]);
+
+    let zone = |name: &str| DomainName::try_from(name).unwrap();
+    let zones = Zones::from_filter(&DomainFilter::new([
+        "example.org",
+        "sub.example.org.",
+        ".example.org",
+        "empty.org",
+    ]));
+
+    assert_eq!(
+        zones.iter().cloned().collect::<Vec<_>>(),
+        vec![
+            zone("example.org"),
+            zone("sub.example.org."),
+            zone("empty.org")
+        ]
+    );
+    assert_eq!(
+        zones.find(&zone("www.Sub.example.org")),
+        Some(&zone("sub.example.org."))
+    );
+    assert_eq!(zones.find(&zone("notexample.org")), None);
+
+    assert_eq!(
+        zones.partition_endpoints(vec![
+            endpoint("www.sub.example.org"),
+            endpoint("example.org"),
+            endpoint("www.example.com"),
+            endpoint("sub.example.org"),
+        ]),
+        Partition {
+            zones: vec![
+                (zone("example.org"), vec![endpoint("example.org")]),
+                (
+                    zone("sub.example.org."),
+                    vec![endpoint("www.sub.example.org"), endpoint("sub.example.org")]
+                ),
+                (zone("empty.org"), vec![]),
+            ],
+            unmatched: vec![endpoint("www.example.com")],
+        }
+    );
+
+    let changes = zones.partition_changes(vec![
+        Change::Create(endpoint("www.sub.example.org")),
+        Change::Update {
+            old: endpoint("www.example.org"),
+            new: endpoint("www.example.org"),
+        },
+        Change::Delete(endpoint("www.example.com")),
+    ]);
+    assert_eq!(changes.zones[0].1.len(), 1);
+    assert_eq!(changes.zones[1].1.len(), 1);
+    assert_eq!(
+        changes.unmatched,
+        vec![Change::Delete(endpoint("www.example.com"))]
+    );
+}
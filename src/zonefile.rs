@@ -0,0 +1,791 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use kubizone_common::{DomainName, FullyQualifiedDomainName, Type};
+use tracing::warn;
+
+use crate::{normalize_target, parse_type, Endpoint, EndpointIdent, Zones};
+
+/// Maximum length of a single `<character-string>`, as per RFC 1035 section 3.3.
+const CHARACTER_STRING_LENGTH: usize = 255;
+
+/// Endpoints belonging to a single DNS zone, which can be rendered as an
+/// RFC 1035 master file using its [`Display`] implementation, and read back
+/// using [`ZoneFile::parse`].
+///
+/// Record names are written relative to the [`origin`](ZoneFile::origin),
+/// and every target of an endpoint is written as a separate resource record.
+/// Set identifiers cannot be represented in a zone file, and are rendered as
+/// comments only.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneFile {
+    /// Origin of the zone, written as the `$ORIGIN` directive.
+    pub origin: DomainName,
+
+    /// Default TTL of the zone, written as the `$TTL` directive.
+    ///
+    /// Endpoints without a [`record_ttl`](Endpoint::record_ttl) inherit this value.
+    pub ttl: Option<i64>,
+
+    /// Endpoints within the zone.
+    pub endpoints: Vec<Endpoint>,
+}
+
+impl ZoneFile {
+    /// Construct an empty zone file for the given origin.
+    pub fn new(origin: DomainName) -> Self {
+        ZoneFile {
+            origin,
+            ttl: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Set the default TTL of the zone.
+    pub fn with_ttl(mut self, ttl: i64) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Partition endpoints into one zone file per zone.
+    ///
+    /// Each endpoint is placed in the most specific zone containing it,
+    /// as determined by [`Zones`]. Endpoints which do not belong to any
+    /// of the zones are returned separately, in their original order.
+    pub fn partition(
+        zones: &[DomainName],
+        endpoints: Vec<Endpoint>,
+    ) -> (Vec<ZoneFile>, Vec<Endpoint>) {
+        let partition = Zones::new(zones.iter().cloned()).partition_endpoints(endpoints);
+
+        let files = partition
+            .zones
+            .into_iter()
+            .map(|(origin, endpoints)| ZoneFile {
+                origin,
+                ttl: None,
+                endpoints,
+            })
+            .collect();
+
+        (files, partition.unmatched)
+    }
+
+    /// Name of the record relative to the origin, `@` for the origin itself.
+    fn relative_name(&self, dns_name: &DomainName) -> String {
+        let name = dns_name.to_fully_qualified();
+
+        match &name - &self.origin.to_fully_qualified() {
+            Ok(relative) if relative.iter().len() == 0 => "@".to_string(),
+            Ok(relative) => relative.to_string(),
+            Err(_) => name.to_string(),
+        }
+    }
+}
+
+/// Presentation format of a single target of the given record type.
+fn rdata(record_type: Type, target: &str) -> String {
+    match record_type {
+        Type::TXT => escape_txt(target),
+        // The primary name server and responsible mailbox must be absolute.
+        Type::SOA => target
+            .split_whitespace()
+            .enumerate()
+            .map(|(index, field)| match index {
+                0 | 1 if !field.ends_with('.') => format!("{field}."),
+                _ => field.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => normalize_target(record_type, target),
+    }
+}
+
+/// Quote and escape a TXT target, splitting it into multiple
+/// `<character-string>`s if necessary.
+///
+/// External-DNS may hand out TXT targets already wrapped in double quotes,
+/// in which case the surrounding quotes are removed before escaping, see
+/// [`normalize_target`].
+fn escape_txt(target: &str) -> String {
+    let target = normalize_target(Type::TXT, target);
+
+    if target.is_empty() {
+        return "\"\"".to_string();
+    }
+
+    let strings: Vec<String> = target
+        .as_bytes()
+        .chunks(CHARACTER_STRING_LENGTH)
+        .map(quote)
+        .collect();
+
+    strings.join(" ")
+}
+
+/// Render bytes as a quoted `<character-string>`, escaping quotes,
+/// backslashes and non-printable characters.
+fn quote(bytes: &[u8]) -> String {
+    let mut escaped = String::from("\"");
+
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(char::from(*byte));
+            }
+            0x20..=0x7e => escaped.push(char::from(*byte)),
+            _ => escaped.push_str(&format!("\\{byte:03}")),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+impl Display for ZoneFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "$ORIGIN {}", self.origin.to_fully_qualified())?;
+
+        if let Some(ttl) = self.ttl {
+            writeln!(f, "$TTL {ttl}")?;
+        }
+
+        for endpoint in &self.endpoints {
+            let name = self.relative_name(&endpoint.identity.dns_name);
+            let record_type = endpoint.identity.record_type;
+
+            if let Some(set_identifier) = &endpoint.set_identifier {
+                writeln!(f, "; set identifier: {set_identifier}")?;
+            }
+
+            for target in &endpoint.targets {
+                write!(f, "{name}\t")?;
+
+                if let Some(ttl) = endpoint.record_ttl {
+                    write!(f, "{ttl}\t")?;
+                }
+
+                writeln!(f, "IN\t{record_type}\t{}", rdata(record_type, target))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Produced when parsing an RFC 1035 master file into a [`ZoneFile`] fails.
+///
+/// Each variant carries the line number on which the error was encountered.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ZoneFileError {
+    /// A quoted string is not terminated before the end of the file.
+    #[error("line {0}: unterminated quoted string")]
+    UnterminatedString(usize),
+
+    /// Parentheses are not balanced.
+    #[error("line {0}: unbalanced parentheses")]
+    UnbalancedParentheses(usize),
+
+    /// A relative name was used before any `$ORIGIN` was known.
+    #[error("line {0}: relative name {1:?} used without an origin")]
+    MissingOrigin(usize, String),
+
+    /// A record omits its owner name, but no previous record exists.
+    #[error("line {0}: record has no owner name")]
+    MissingOwner(usize),
+
+    /// A record or directive is missing required fields.
+    #[error("line {0}: incomplete record or directive")]
+    Incomplete(usize),
+
+    /// A name is not a valid domain name.
+    #[error("line {0}: invalid domain name {1:?}")]
+    InvalidName(usize, String),
+
+    /// A TTL is neither a number of seconds, nor a duration like `1h30m`.
+    #[error("line {0}: invalid ttl {1:?}")]
+    InvalidTtl(usize, String),
+
+    /// The record type is not known.
+    #[error("line {0}: unknown record type {1:?}")]
+    UnknownType(usize, String),
+
+    /// Only the `IN` class is supported.
+    #[error("line {0}: unsupported class {1}")]
+    UnsupportedClass(usize, String),
+
+    /// Directives other than `$ORIGIN` and `$TTL`, such as `$INCLUDE`, are not supported.
+    #[error("line {0}: unsupported directive {1}")]
+    UnsupportedDirective(usize, String),
+}
+
+/// Token of a master file, after comments and parentheses have been removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// Unquoted word, as written.
+    Word(String),
+    /// Contents of a quoted string, with escapes resolved.
+    Quoted(Vec<u8>),
+}
+
+/// Logical line of a master file, which may span multiple physical
+/// lines when parentheses are used.
+#[derive(Debug, Default)]
+struct Line {
+    /// Number of the physical line on which the logical line starts.
+    number: usize,
+    /// True if the line starts with whitespace, meaning the owner is omitted.
+    blank_owner: bool,
+    tokens: Vec<Token>,
+}
+
+/// Split a master file into logical lines of tokens.
+fn tokenize(input: &str) -> Result<Vec<Line>, ZoneFileError> {
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    let mut number = 1;
+    let mut depth = 0;
+    let mut line_start = true;
+
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if line_start && depth == 0 {
+            line = Line {
+                number,
+                blank_owner: c == ' ' || c == '\t',
+                tokens: Vec::new(),
+            };
+        }
+        line_start = false;
+
+        match c {
+            '\n' => {
+                number += 1;
+                line_start = true;
+
+                if depth == 0 && !line.tokens.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+            }
+            ';' => while chars.next_if(|c| *c != '\n').is_some() {},
+            ' ' | '\t' | '\r' => {}
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(ZoneFileError::UnbalancedParentheses(number)),
+            ')' => depth -= 1,
+            '"' => {
+                let start = number;
+                let mut bytes = Vec::new();
+
+                loop {
+                    match chars.next() {
+                        None => return Err(ZoneFileError::UnterminatedString(start)),
+                        Some('"') => break,
+                        Some('\\') => unescape(&mut chars, &mut bytes),
+                        Some(c) => {
+                            if c == '\n' {
+                                number += 1;
+                            }
+
+                            let mut buffer = [0; 4];
+                            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                    }
+                }
+
+                line.tokens.push(Token::Quoted(bytes));
+            }
+            c => {
+                let mut word = String::from(c);
+
+                if c == '\\' {
+                    word.extend(chars.next());
+                }
+
+                while let Some(c) = chars
+                    .next_if(|c| !matches!(c, ' ' | '\t' | '\r' | '\n' | ';' | '(' | ')' | '"'))
+                {
+                    word.push(c);
+
+                    if c == '\\' {
+                        word.extend(chars.next());
+                    }
+                }
+
+                line.tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    if depth != 0 {
+        return Err(ZoneFileError::UnbalancedParentheses(number));
+    }
+
+    if !line.tokens.is_empty() {
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+/// Resolve a backslash escape within a quoted string, either `\DDD` or `\X`.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, bytes: &mut Vec<u8>) {
+    let mut digits = String::new();
+
+    while digits.len() < 3 {
+        match chars.next_if(char::is_ascii_digit) {
+            Some(digit) => digits.push(digit),
+            None => break,
+        }
+    }
+
+    match digits.parse::<u8>() {
+        Ok(byte) if digits.len() == 3 => bytes.push(byte),
+        _ => {
+            bytes.extend_from_slice(digits.as_bytes());
+
+            if digits.is_empty() {
+                if let Some(c) = chars.next() {
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Parse a TTL, either as a number of seconds or as a BIND-style duration like `1h30m`.
+fn parse_ttl(ttl: &str) -> Option<i64> {
+    if let Ok(seconds) = ttl.parse() {
+        return Some(seconds);
+    }
+
+    let mut total = 0i64;
+    let mut digits = String::new();
+
+    for c in ttl.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+
+        total = total.checked_add(digits.parse::<i64>().ok()?.checked_mul(unit)?)?;
+        digits.clear();
+    }
+
+    digits.is_empty().then_some(total)
+}
+
+/// Parser state while reading the records of a master file.
+struct Parser {
+    origin: Option<FullyQualifiedDomainName>,
+    ttl: Option<i64>,
+    owner: Option<DomainName>,
+}
+
+impl Parser {
+    /// Resolve a possibly relative name against the current origin,
+    /// returning it without a trailing dot.
+    fn absolute(&self, line: usize, name: &str) -> Result<String, ZoneFileError> {
+        if let Some(name) = name.strip_suffix('.') {
+            return Ok(name.to_string());
+        }
+
+        let Some(origin) = &self.origin else {
+            return Err(ZoneFileError::MissingOrigin(line, name.to_string()));
+        };
+
+        let origin = origin.to_string();
+        let origin = origin.trim_end_matches('.');
+
+        Ok(match name {
+            "@" => origin.to_string(),
+            name if origin.is_empty() => name.to_string(),
+            name => format!("{name}.{origin}"),
+        })
+    }
+
+    fn name(&self, line: usize, name: &str) -> Result<DomainName, ZoneFileError> {
+        let absolute = self.absolute(line, name)?;
+
+        DomainName::try_from(absolute.as_str())
+            .map_err(|_| ZoneFileError::InvalidName(line, name.to_string()))
+    }
+
+    fn directive(&mut self, line: &Line, directive: &str) -> Result<(), ZoneFileError> {
+        let Some(Token::Word(argument)) = line.tokens.get(1) else {
+            return Err(ZoneFileError::Incomplete(line.number));
+        };
+
+        match directive.to_ascii_uppercase().as_str() {
+            "$ORIGIN" => {
+                let origin = self.name(line.number, argument)?;
+                self.origin = Some(origin.to_fully_qualified());
+            }
+            "$TTL" => {
+                self.ttl = Some(
+                    parse_ttl(argument)
+                        .ok_or_else(|| ZoneFileError::InvalidTtl(line.number, argument.clone()))?,
+                );
+            }
+            _ => {
+                return Err(ZoneFileError::UnsupportedDirective(
+                    line.number,
+                    directive.to_string(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a resource record into its owner, type, TTL and target.
+    fn record(
+        &mut self,
+        line: &Line,
+    ) -> Result<(DomainName, Type, Option<i64>, String), ZoneFileError> {
+        let mut tokens = line.tokens.iter();
+
+        let owner = if line.blank_owner {
+            self.owner
+                .clone()
+                .ok_or(ZoneFileError::MissingOwner(line.number))?
+        } else {
+            match tokens.next() {
+                Some(Token::Word(owner)) => self.name(line.number, owner)?,
+                _ => return Err(ZoneFileError::MissingOwner(line.number)),
+            }
+        };
+        self.owner = Some(owner.clone());
+
+        // TTL and class may appear in either order, and are both optional.
+        let mut ttl = None;
+        let record_type = loop {
+            let Some(Token::Word(word)) = tokens.next() else {
+                return Err(ZoneFileError::Incomplete(line.number));
+            };
+
+            if let Some(seconds) = parse_ttl(word).filter(|_| ttl.is_none()) {
+                ttl = Some(seconds);
+                continue;
+            }
+
+            match word.to_ascii_uppercase().as_str() {
+                "IN" => continue,
+                class @ ("CH" | "HS" | "CS" | "ANY") => {
+                    return Err(ZoneFileError::UnsupportedClass(
+                        line.number,
+                        class.to_string(),
+                    ))
+                }
+                _ => {}
+            }
+
+            break parse_type(word)
+                .ok_or_else(|| ZoneFileError::UnknownType(line.number, word.clone()))?;
+        };
+
+        let rdata: Vec<&Token> = tokens.collect();
+        if rdata.is_empty() {
+            return Err(ZoneFileError::Incomplete(line.number));
+        }
+
+        let target = self.target(line.number, record_type, &rdata)?;
+
+        Ok((owner, record_type, ttl.or(self.ttl), target))
+    }
+
+    /// Convert the record data into an endpoint target.
+    ///
+    /// TXT character-strings are concatenated and unquoted, and domain names
+    /// in CNAME, NS, PTR, DNAME, MX, SRV and SOA records are made absolute.
+    fn target(
+        &self,
+        line: usize,
+        record_type: Type,
+        rdata: &[&Token],
+    ) -> Result<String, ZoneFileError> {
+        let mut fields: Vec<String> = rdata
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.clone(),
+                Token::Quoted(bytes) if record_type == Type::TXT => {
+                    String::from_utf8_lossy(bytes).into_owned()
+                }
+                Token::Quoted(bytes) => quote(bytes),
+            })
+            .collect();
+
+        match record_type {
+            Type::TXT => return Ok(fields.concat()),
+            Type::CNAME | Type::NS | Type::PTR | Type::DNAME | Type::MX | Type::SRV => {
+                if let Some(name) = fields.last_mut() {
+                    *name = self.absolute(line, name)?;
+                }
+            }
+            Type::SOA => {
+                for name in fields.iter_mut().take(2) {
+                    *name = self.absolute(line, name)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(fields.join(" "))
+    }
+}
+
+impl ZoneFile {
+    /// Parse an RFC 1035 master file, resolving relative names against
+    /// the given origin until a `$ORIGIN` directive is encountered.
+    ///
+    /// Resource records sharing a name and type are grouped into a single
+    /// endpoint with multiple targets, taking the TTL of the first record.
+    /// Later records of the same set with a different TTL are logged as a warning.
+    /// Records without an explicit TTL inherit the `$TTL` directive, if any.
+    pub fn parse(input: &str, origin: Option<DomainName>) -> Result<Self, ZoneFileError> {
+        let mut parser = Parser {
+            origin: origin.as_ref().map(DomainName::to_fully_qualified),
+            ttl: None,
+            owner: None,
+        };
+
+        let mut index: HashMap<EndpointIdent, usize> = HashMap::new();
+        let mut has_origin = origin.is_some();
+        let mut zone = ZoneFile {
+            origin: origin.unwrap_or_default(),
+            ttl: None,
+            endpoints: Vec::new(),
+        };
+
+        for line in tokenize(input)? {
+            match line.tokens.first() {
+                Some(Token::Word(directive)) if directive.starts_with('$') && !line.blank_owner => {
+                    parser.directive(&line, directive)?;
+
+                    // The first origin and TTL declared become those of the zone.
+                    if let Some(origin) = parser.origin.clone().filter(|_| !has_origin) {
+                        zone.origin = DomainName::from(origin);
+                        has_origin = true;
+                    }
+                    zone.ttl = zone.ttl.or(parser.ttl);
+                }
+                _ => {
+                    let (dns_name, record_type, ttl, target) = parser.record(&line)?;
+
+                    let identity = EndpointIdent {
+                        dns_name,
+                        record_type,
+                    };
+
+                    match index.get(&identity) {
+                        Some(position) => {
+                            let endpoint = &mut zone.endpoints[*position];
+
+                            if endpoint.record_ttl != ttl {
+                                warn!(
+                                    "line {}: TTL of {} {} differs from earlier records in the set, keeping {:?}",
+                                    line.number,
+                                    identity.dns_name,
+                                    identity.record_type,
+                                    endpoint.record_ttl
+                                );
+                            }
+
+                            if !endpoint.targets.contains(&target) {
+                                endpoint.targets.push(target);
+                            }
+                        }
+                        None => {
+                            index.insert(identity.clone(), zone.endpoints.len());
+                            zone.endpoints.push(Endpoint {
+                                identity,
+                                set_identifier: None,
+                                targets: vec![target],
+                                record_ttl: ttl,
+                                labels: HashMap::default(),
+                                provider_specific: Vec::new(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(zone)
+    }
+}
+
+impl FromStr for ZoneFile {
+    type Err = ZoneFileError;
+
+    /// Parse an RFC 1035 master file, which must declare its `$ORIGIN`
+    /// before using relative names.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        ZoneFile::parse(input, None)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn zone_file_export() {
+    use crate::EndpointDiff;
+
+    let endpoint = |name: &str, record_type: Type, targets: &[&str], ttl: Option<i64>| Endpoint {
+        record_ttl: ttl,
+        ..Endpoint::test(name, record_type, targets)
+    };
+
+    let (files, unmatched) = ZoneFile::partition(
+        &[
+            DomainName::try_from("example.org.").unwrap(),
+            DomainName::try_from("sub.example.org.").unwrap(),
+        ],
+        vec![
+            endpoint("example.org", Type::MX, &["10 Mail.example.org"], None),
+            endpoint(
+                "www.example.org",
+                Type::A,
+                &["192.168.0.1", "192.168.0.2"],
+                Some(60),
+            ),
+            endpoint("www.sub.example.org", Type::CNAME, &["example.org"], None),
+            endpoint(
+                "txt.example.org",
+                Type::TXT,
+                &[
+                    "\"heritage=external-dns,external-dns/owner=default\"",
+                    "say \"hi\"\\\n",
+                ],
+                None,
+            ),
+            endpoint("www.example.com", Type::A, &["192.168.0.3"], None),
+        ],
+    );
+
+    assert_eq!(
+        unmatched,
+        vec![endpoint("www.example.com", Type::A, &["192.168.0.3"], None)]
+    );
+
+    assert_eq!(
+        files[0].clone().with_ttl(300).to_string(),
+        "$ORIGIN example.org.
+$TTL 300
+@\tIN\tMX\t10 mail.example.org.
+www\t60\tIN\tA\t192.168.0.1
+www\t60\tIN\tA\t192.168.0.2
+txt\tIN\tTXT\t\"heritage=external-dns,external-dns/owner=default\"
+txt\tIN\tTXT\t\"say \\\"hi\\\"\\\\\\010\"
+"
+    );
+
+    assert_eq!(
+        files[1].to_string(),
+        "$ORIGIN sub.example.org.
+www\tIN\tCNAME\texample.org.
+"
+    );
+
+    // Quoted TXT targets survive being exported and imported again.
+    let imported = ZoneFile::from_str(&files[0].to_string()).unwrap();
+    assert_eq!(
+        files[0].endpoints.clone().difference(imported.endpoints),
+        vec![]
+    );
+
+    let long = "a".repeat(300);
+    assert_eq!(
+        escape_txt(&long),
+        format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn zone_file_import() {
+    let endpoint = |name: &str, record_type: Type, targets: &[&str], ttl: i64| Endpoint {
+        record_ttl: Some(ttl),
+        ..Endpoint::test(name, record_type, targets)
+    };
+
+    let zone = ZoneFile::from_str(
+        r#"$ORIGIN example.org.
+$TTL 1h
+@       IN  SOA ns1 hostmaster (
+                2024010101 ; serial
+                1d 2h 4w 1h )
+        IN  MX  10 mail
+www  60 IN  A   192.168.0.1
+     IN 60  A   192.168.0.2
+     IN     A   192.168.0.1 ; duplicate
+txt         TXT "v=spf1 " "include:example.com"
+            TXT ( "say \"hi\"" "\059" )
+alias       CNAME www.example.com.
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(zone.origin, DomainName::try_from("example.org.").unwrap());
+    assert_eq!(zone.ttl, Some(3600));
+    assert_eq!(
+        zone.endpoints,
+        vec![
+            endpoint(
+                "example.org",
+                Type::SOA,
+                &["ns1.example.org hostmaster.example.org 2024010101 1d 2h 4w 1h"],
+                3600
+            ),
+            endpoint("example.org", Type::MX, &["10 mail.example.org"], 3600),
+            endpoint(
+                "www.example.org",
+                Type::A,
+                &["192.168.0.1", "192.168.0.2"],
+                60
+            ),
+            endpoint(
+                "txt.example.org",
+                Type::TXT,
+                &["v=spf1 include:example.com", "say \"hi\";"],
+                3600
+            ),
+            endpoint("alias.example.org", Type::CNAME, &["www.example.com"], 3600),
+        ]
+    );
+
+    // Exported zone files can be imported again.
+    assert_eq!(ZoneFile::from_str(&zone.to_string()).unwrap(), zone);
+
+    assert_eq!(
+        ZoneFile::from_str("www IN A 192.168.0.1"),
+        Err(ZoneFileError::MissingOrigin(1, "www".to_string()))
+    );
+    assert_eq!(
+        ZoneFile::parse(
+            "www IN A 192.168.0.1\n$INCLUDE other.zone",
+            Some(DomainName::try_from("example.org.").unwrap())
+        ),
+        Err(ZoneFileError::UnsupportedDirective(
+            2,
+            "$INCLUDE".to_string()
+        ))
+    );
+    assert_eq!(
+        ZoneFile::from_str("$ORIGIN example.org.\ntxt TXT ( \"unterminated )\n"),
+        Err(ZoneFileError::UnterminatedString(2))
+    );
+}
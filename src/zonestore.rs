@@ -0,0 +1,238 @@
+use async_trait::async_trait;
+use futures_util::future::try_join_all;
+use kubizone_common::DomainName;
+use tracing::{instrument, trace};
+
+use crate::{Change, DomainFilter, Endpoint, ErrorKind, Provider, ProviderError, Zones};
+
+/// Backend which manages DNS records one zone at a time.
+///
+/// Implementing this trait and wrapping the backend in a [`ZoneStoreProvider`]
+/// turns it into a [`Provider`], which takes care of distributing requests
+/// across the zones.
+#[async_trait]
+pub trait ZoneStore: Send + Sync {
+    /// Error returned by the store, which must also be able to represent
+    /// changes rejected by the [`ZoneStoreProvider`] for not belonging to any zone.
+    type Error: ProviderError + Send + From<UnmatchedZone>;
+
+    /// List the zones managed by the backend.
+    async fn zones(&self) -> Result<Vec<DomainName>, Self::Error>;
+
+    /// List all endpoints within the zone.
+    async fn list(&self, zone: &DomainName) -> Result<Vec<Endpoint>, Self::Error>;
+
+    /// Apply the changes to the zone.
+    ///
+    /// Only called with changes belonging to the zone, and never with an empty batch.
+    async fn apply(&self, zone: &DomainName, changes: Vec<Change>) -> Result<(), Self::Error>;
+}
+
+/// Produced by a [`ZoneStoreProvider`] when a change targets a domain
+/// which does not belong to any of the store's zones.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("domain {0} does not belong to any zone")]
+pub struct UnmatchedZone(pub DomainName);
+
+impl ProviderError for UnmatchedZone {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::InvalidChanges
+    }
+}
+
+/// [`Provider`] backed by a [`ZoneStore`].
+///
+/// The domain filter returned by [`Provider::init`] includes every zone,
+/// endpoints are listed from all zones concurrently, and changes are
+/// grouped by the most specific zone containing them, see [`Zones`].
+///
+/// If any change does not belong to a zone, the whole batch is rejected
+/// with an [`UnmatchedZone`] error before any of the changes are applied.
+#[derive(Debug, Clone)]
+pub struct ZoneStoreProvider<S> {
+    store: S,
+}
+
+impl<S: ZoneStore> ZoneStoreProvider<S> {
+    /// Wrap the given store.
+    pub fn new(store: S) -> Self {
+        ZoneStoreProvider { store }
+    }
+
+    /// The wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+#[async_trait]
+impl<S: ZoneStore> Provider for ZoneStoreProvider<S> {
+    type Error = S::Error;
+
+    async fn init(&self) -> Result<DomainFilter, Self::Error> {
+        Ok(DomainFilter::new(self.store.zones().await?))
+    }
+
+    async fn healthz(&self) -> Result<String, Self::Error> {
+        Ok("ok".to_string())
+    }
+
+    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
+        let zones = self.store.zones().await?;
+
+        let endpoints = try_join_all(zones.iter().map(|zone| self.store.list(zone))).await?;
+
+        Ok(endpoints.into_iter().flatten().collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_records(&self, changes: Vec<Change>) -> Result<(), Self::Error> {
+        let zones = Zones::new(self.store.zones().await?);
+        let partition = zones.partition_changes(changes);
+
+        if let Some(change) = partition.unmatched.first() {
+            let endpoint = match change {
+                Change::Update { old, .. } => old,
+                Change::Delete(endpoint) | Change::Create(endpoint) => endpoint,
+            };
+
+            return Err(UnmatchedZone(endpoint.identity.dns_name.clone()).into());
+        }
+
+        for (zone, changes) in partition.zones {
+            if changes.is_empty() {
+                continue;
+            }
+
+            trace!("applying {} changes to zone {zone}", changes.len());
+            self.store.apply(&zone, changes).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn adjust_endpoints(
+        &self,
+        endpoints: Vec<Endpoint>,
+    ) -> Result<Vec<Endpoint>, Self::Error> {
+        Ok(endpoints)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn zone_store_provider() {
+    use kubizone_common::Type;
+    use std::sync::Mutex;
+
+    struct Store {
+        zones: Mutex<Vec<(DomainName, Vec<Endpoint>)>>,
+        batches: Mutex<Vec<(DomainName, usize)>>,
+    }
+
+    #[async_trait]
+    impl ZoneStore for Store {
+        type Error = UnmatchedZone;
+
+        async fn zones(&self) -> Result<Vec<DomainName>, UnmatchedZone> {
+            Ok(self
+                .zones
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(zone, _)| zone.clone())
+                .collect())
+        }
+
+        async fn list(&self, zone: &DomainName) -> Result<Vec<Endpoint>, UnmatchedZone> {
+            self.zones
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(name, _)| name == zone)
+                .map(|(_, endpoints)| endpoints.clone())
+                .ok_or_else(|| UnmatchedZone(zone.clone()))
+        }
+
+        async fn apply(
+            &self,
+            zone: &DomainName,
+            changes: Vec<Change>,
+        ) -> Result<(), UnmatchedZone> {
+            self.batches
+                .lock()
+                .unwrap()
+                .push((zone.clone(), changes.len()));
+
+            let mut zones = self.zones.lock().unwrap();
+            let (_, endpoints) = zones.iter_mut().find(|(name, _)| name == zone).unwrap();
+            for change in changes {
+                match change {
+                    Change::Create(endpoint) => endpoints.push(endpoint),
+                    Change::Delete(endpoint) => endpoints.retain(|e| *e != endpoint),
+                    Change::Update { old, new } => {
+                        endpoints.retain(|e| *e != old);
+                        endpoints.push(new);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let endpoint = |name: &str| Endpoint::test(name, Type::A, &["192.168.0.1"]);
+    let zone = |name: &str| DomainName::try_from(name).unwrap();
+
+    let provider = ZoneStoreProvider::new(Store {
+        zones: Mutex::new(vec![
+            (zone("example.org"), vec![endpoint("www.example.org")]),
+            (zone("sub.example.org"), vec![]),
+            (zone("example.com"), vec![endpoint("example.com")]),
+        ]),
+        batches: Mutex::default(),
+    });
+
+    assert_eq!(
+        provider.init().await.unwrap(),
+        DomainFilter::new(["example.org", "sub.example.org", "example.com"])
+    );
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![endpoint("www.example.org"), endpoint("example.com")]
+    );
+
+    // Changes outside of every zone reject the whole batch.
+    assert_eq!(
+        provider
+            .set_records(vec![
+                Change::Create(endpoint("api.example.org")),
+                Change::Create(endpoint("www.example.net")),
+            ])
+            .await,
+        Err(UnmatchedZone(zone("www.example.net")))
+    );
+    assert!(provider.store().batches.lock().unwrap().is_empty());
+
+    provider
+        .set_records(vec![
+            Change::Create(endpoint("www.sub.example.org")),
+            Change::Create(endpoint("api.example.org")),
+            Change::Delete(endpoint("www.example.org")),
+        ])
+        .await
+        .unwrap();
+
+    // Changes are batched per zone, skipping zones without changes.
+    assert_eq!(
+        *provider.store().batches.lock().unwrap(),
+        vec![(zone("example.org"), 2), (zone("sub.example.org"), 1)]
+    );
+    assert_eq!(
+        provider.get_records().await.unwrap(),
+        vec![
+            endpoint("api.example.org"),
+            endpoint("www.sub.example.org"),
+            endpoint("example.com"),
+        ]
+    );
+}
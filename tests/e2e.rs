@@ -1,102 +1,17 @@
 use std::{
     collections::HashMap,
+    fmt::Display,
     net::{Ipv4Addr, SocketAddrV4},
-    sync::Arc,
+    time::Duration,
 };
 
 use axum::async_trait;
-use external_dns_sdk::{Change, Client, Endpoint, EndpointDiff, EndpointIdent, Provider};
+use external_dns_sdk::{
+    Change, Client, DomainFilter, Endpoint, EndpointDiff, EndpointIdent, Error, ErrorBody,
+    ErrorKind, InMemoryProvider, Provider, ProviderError, Server,
+};
 use kubizone_common::{DomainName, Type};
-use tokio::sync::RwLock;
-use tracing::{debug, info, info_span, instrument, level_filters::LevelFilter, trace};
-
-struct DebugProvider {
-    inner: Arc<RwLock<Vec<Endpoint>>>,
-}
-
-impl DebugProvider {
-    pub fn new() -> Self {
-        DebugProvider {
-            inner: Arc::new(RwLock::new(vec![])),
-        }
-    }
-
-    async fn find_index(&self, needle: &Endpoint) -> Option<usize> {
-        self.inner
-            .read()
-            .await
-            .iter()
-            .enumerate()
-            .find_map(|(index, endpoint)| (endpoint.identity == needle.identity).then_some(index))
-    }
-}
-
-#[async_trait]
-impl Provider for DebugProvider {
-    type Error = &'static str;
-
-    #[instrument(skip(self))]
-    async fn init(&self) -> Result<Vec<DomainName>, Self::Error> {
-        info_span!("init");
-
-        info!("initializing");
-        Ok(Vec::new())
-    }
-
-    #[instrument(skip(self))]
-    async fn healthz(&self) -> Result<String, Self::Error> {
-        Ok("ok".to_string())
-    }
-
-    #[instrument(skip(self))]
-    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
-        Ok(self.inner.read().await.clone())
-    }
-
-    #[instrument(skip(self))]
-    async fn set_records(&self, changes: Vec<Change>) -> Result<(), Self::Error> {
-        info_span!("set_records");
-        for change in changes {
-            match change {
-                Change::Update { old, new } => {
-                    trace!(
-                        "updating {} from {} to {}",
-                        old.identity.dns_name,
-                        old.targets.join(", "),
-                        new.targets.join(", ")
-                    );
-
-                    if let Some(index) = self.find_index(&old).await {
-                        self.inner.write().await[index] = new;
-                    }
-                }
-                Change::Delete(endpoint) => {
-                    trace!("deleting {}", endpoint.identity.dns_name,);
-                    if let Some(index) = self.find_index(&endpoint).await {
-                        self.inner.write().await.remove(index);
-                    }
-                }
-                Change::Create(endpoint) => {
-                    trace!("creating {}", endpoint.identity.dns_name,);
-                    self.inner.write().await.push(endpoint)
-                }
-            }
-        }
-        Ok(())
-    }
-
-    #[instrument(skip(self))]
-    async fn adjust_endpoints(
-        &self,
-        endpoints: Vec<Endpoint>,
-    ) -> Result<Vec<Endpoint>, Self::Error> {
-        let changes = self.inner.read().await.clone().difference(endpoints);
-        debug!("{changes:?}");
-
-        self.set_records(changes).await?;
-        self.get_records().await
-    }
-}
+use tracing::level_filters::LevelFilter;
 
 #[tokio::test]
 async fn main() {
@@ -104,16 +19,22 @@ async fn main() {
         .with_max_level(LevelFilter::TRACE)
         .init();
 
-    let server = tokio::spawn(async move {
-        external_dns_sdk::serve(
-            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12333).into(),
-            DebugProvider::new(),
-        )
+    let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+
+    let provider = InMemoryProvider::new().with_domain_filter(DomainFilter::new(["org"]));
+    let server = Server::builder(provider.clone())
+        .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())
+        .with_graceful_shutdown(async move {
+            shutdown_signal.await.ok();
+        })
+        .build()
         .await
-    });
+        .unwrap();
 
-    let client = Client::new("http://localhost:12333").unwrap();
+    let client = Client::new(format!("http://{}", server.local_addr().unwrap())).unwrap();
+    let server = tokio::spawn(server.run());
 
+    assert_eq!(client.init().await.unwrap(), DomainFilter::new(["org"]));
     assert_eq!(client.healthz().await.unwrap(), "ok".to_string());
     assert_eq!(client.adjust_endpoints(vec![]).await.unwrap(), vec![]);
     assert_eq!(client.get_records().await.unwrap(), vec![]);
@@ -238,6 +159,178 @@ async fn main() {
         ]
     );
 
-    server.abort();
-    server.await.ok();
+    // Adjusting endpoints must not modify the provider's state.
+    let adjusted = client
+        .adjust_endpoints(vec![Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("alias.org").unwrap(),
+                record_type: Type::CNAME,
+            },
+            set_identifier: None,
+            targets: vec!["Create.ORG".to_string(), "create.org.".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        }])
+        .await
+        .unwrap();
+
+    assert_eq!(adjusted[0].targets, vec!["create.org.".to_string()]);
+    assert_eq!(provider.endpoints().await.len(), 2);
+
+    // Changes outside of the domain filter are rejected.
+    match client
+        .set_records(vec![Change::Create(Endpoint {
+            identity: EndpointIdent {
+                dns_name: DomainName::try_from("example.com").unwrap(),
+                record_type: Type::A,
+            },
+            set_identifier: None,
+            targets: vec!["192.168.0.1".to_string()],
+            record_ttl: Some(300),
+            labels: HashMap::default(),
+            provider_specific: Vec::new(),
+        })])
+        .await
+    {
+        Err(Error::Webhook(status, _)) => assert_eq!(status.as_u16(), 400),
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn split_health_listener() {
+    let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+
+    let server = Server::builder(InMemoryProvider::new())
+        .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())
+        .health_bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())
+        .with_graceful_shutdown(async move {
+            shutdown_signal.await.ok();
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let api_addr = server.local_addr().unwrap();
+    let health_addr = server.health_local_addr().unwrap().unwrap();
+    let api = Client::new(format!("http://{api_addr}")).unwrap();
+    let health = Client::new(format!("http://{health_addr}")).unwrap();
+    let server = tokio::spawn(server.run());
+
+    assert_eq!(health.healthz().await.unwrap(), "ok".to_string());
+    assert!(health.get_records().await.is_err());
+    assert_eq!(api.get_records().await.unwrap(), vec![]);
+
+    // Metrics are only served alongside the health check.
+    let metrics = |addr| async move {
+        reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .unwrap()
+            .status()
+            .as_u16()
+    };
+    assert_eq!(metrics(api_addr).await, 404);
+    #[cfg(feature = "metrics")]
+    assert_eq!(metrics(health_addr).await, 200);
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+}
+
+struct Throttled;
+
+impl Display for Throttled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("backend is throttling requests")
+    }
+}
+
+impl ProviderError for Throttled {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::RateLimited
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        Some(Duration::from_secs(30))
+    }
+
+    fn structured(&self) -> bool {
+        true
+    }
+}
+
+struct ThrottledProvider;
+
+#[async_trait]
+impl Provider for ThrottledProvider {
+    type Error = Throttled;
+
+    async fn init(&self) -> Result<DomainFilter, Self::Error> {
+        Ok(DomainFilter::default())
+    }
+
+    async fn healthz(&self) -> Result<String, Self::Error> {
+        Ok("ok".to_string())
+    }
+
+    async fn get_records(&self) -> Result<Vec<Endpoint>, Self::Error> {
+        Err(Throttled)
+    }
+
+    async fn set_records(&self, _changes: Vec<Change>) -> Result<(), Self::Error> {
+        Err(Throttled)
+    }
+
+    async fn adjust_endpoints(
+        &self,
+        _endpoints: Vec<Endpoint>,
+    ) -> Result<Vec<Endpoint>, Self::Error> {
+        Err(Throttled)
+    }
+}
+
+#[tokio::test]
+async fn structured_provider_errors() {
+    let (shutdown, shutdown_signal) = tokio::sync::oneshot::channel::<()>();
+
+    let server = Server::builder(ThrottledProvider)
+        .bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())
+        .with_graceful_shutdown(async move {
+            shutdown_signal.await.ok();
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let client = Client::new(format!("http://{}", server.local_addr().unwrap())).unwrap();
+    let server = tokio::spawn(server.run());
+
+    let expected = ErrorBody {
+        kind: ErrorKind::RateLimited,
+        message: "backend is throttling requests".to_string(),
+        retry_after: Some(30),
+    };
+
+    match client.get_records().await {
+        Err(Error::Provider(status, body)) => {
+            assert_eq!(status.as_u16(), 429);
+            assert_eq!(body, expected);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    match client.set_records(vec![]).await {
+        Err(Error::Provider(status, body)) => {
+            assert_eq!(status.as_u16(), 429);
+            assert_eq!(body, expected);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
 }